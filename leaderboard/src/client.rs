@@ -0,0 +1,136 @@
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+
+use crate::http::{encode, read_message, write_message};
+use crate::{LeaderboardEntry, RunSubmission, SubmitResult};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the runs are posted and the tables come from, the calls may block.
+pub trait ScoreBackend: Send + Sync {
+    fn submit(&self, run: &RunSubmission) -> anyhow::Result<SubmitResult>;
+
+    /// The boards having the ranked runs, sorted
+    fn boards(&self) -> anyhow::Result<Vec<String>>;
+
+    /// The best ranked runs of the board
+    fn top(&self, board: &str, count: usize) -> anyhow::Result<Vec<LeaderboardEntry>>;
+}
+
+/// The leaderboard server over http and json.
+pub struct HttpBackend {
+    /// `host:port`
+    address: String,
+}
+
+impl HttpBackend {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self { address: address.into() }
+    }
+
+    fn request<T: DeserializeOwned>(&self, method: &str, path: &str, body: &[u8]) -> anyhow::Result<T> {
+        let addr = self.address.to_socket_addrs()?.next().ok_or(anyhow!("No address for {}", self.address))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        write_message(&mut stream, &format!("{} {} HTTP/1.1", method, path), Some(&self.address), body)?;
+        let response = read_message(&mut stream)?;
+        if response.start[1] != "200" {
+            return Err(anyhow!("The server said {}: {}", response.start[1..].join(" "), String::from_utf8_lossy(&response.body)));
+        }
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+}
+
+impl ScoreBackend for HttpBackend {
+    fn submit(&self, run: &RunSubmission) -> anyhow::Result<SubmitResult> {
+        self.request("POST", "/runs", &serde_json::to_vec(run)?)
+    }
+
+    fn boards(&self) -> anyhow::Result<Vec<String>> {
+        self.request("GET", "/boards", &[])
+    }
+
+    fn top(&self, board: &str, count: usize) -> anyhow::Result<Vec<LeaderboardEntry>> {
+        self.request("GET", &format!("/boards/{}/top?count={}", encode(board), count), &[])
+    }
+}
+
+/// The answer of the background call
+pub struct Pending<T>(Receiver<anyhow::Result<T>>);
+
+impl<T> Pending<T> {
+    /// `None` while waiting
+    pub fn try_take(&self) -> Option<anyhow::Result<T>> {
+        match self.0.try_recv() {
+            Ok(x) => Some(x),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("The request is lost"))),
+        }
+    }
+}
+
+/// Calls the backend in the background so the game never waits for the network.
+#[derive(Default, Clone)]
+pub struct Leaderboard {
+    backend: Option<Arc<dyn ScoreBackend>>,
+}
+
+impl Leaderboard {
+    pub fn new(backend: impl ScoreBackend + 'static) -> Self {
+        Self { backend: Some(Arc::new(backend)) }
+    }
+
+    /// The http server at the address, disabled if it is empty
+    pub fn http(address: &str) -> Self {
+        let address = address.trim();
+        if address.is_empty() { Self::default() } else { Self::new(HttpBackend::new(address)) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.backend.is_some()
+    }
+
+    fn spawn<T: Send + 'static>(&self, f: impl FnOnce(&dyn ScoreBackend) -> anyhow::Result<T> + Send + 'static) -> Pending<T> {
+        let (tx, rx) = channel();
+        match self.backend.clone() {
+            Some(backend) => {
+                std::thread::spawn(move || {
+                    let _ = tx.send(f(backend.as_ref()));
+                });
+            }
+            None => {
+                let _ = tx.send(Err(anyhow!("No leaderboard server")));
+            }
+        }
+        Pending(rx)
+    }
+
+    /// Post the run, the result is only logged
+    pub fn submit(&self, run: RunSubmission) {
+        if self.backend.is_none() {
+            return;
+        }
+        self.spawn(move |backend| {
+            match backend.submit(&run) {
+                Ok(result) => log::info!("Posted the {} run of {}, rank {:?}", run.board, run.player, result.rank),
+                Err(e) => log::warn!("Post the {} run of {} failed for {:?}", run.board, run.player, e),
+            }
+            Ok(())
+        });
+    }
+
+    pub fn boards(&self) -> Pending<Vec<String>> {
+        self.spawn(|backend| backend.boards())
+    }
+
+    pub fn top(&self, board: &str, count: usize) -> Pending<Vec<LeaderboardEntry>> {
+        let board = board.to_string();
+        self.spawn(move |backend| backend.top(&board, count))
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{intervals, mean, rolling_cps, stddev};
+
+/// Do not judge the sessions with fewer intervals
+const MIN_INTERVALS: usize = 20;
+/// Humans hardly keep the interval stddev under this fraction of the mean
+const MIN_HUMAN_CV: f64 = 0.03;
+/// The intervals within this seconds are the same
+const PERIOD_TOLERANCE: f64 = 0.0005;
+/// Machines repeat the same interval more than this fraction of the intervals
+const MAX_HUMAN_PERIODIC: f64 = 0.5;
+/// Nobody keeps this cps for [`SUSTAIN`]
+const MAX_HUMAN_CPS: f64 = 30.0;
+const SUSTAIN: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerdictLevel {
+    Clean,
+    Suspicious,
+    Macro,
+}
+
+/// How much the clicks look machine-generated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub level: VerdictLevel,
+    /// In `[0, 1]`, combined from all the checks
+    pub confidence: f64,
+    pub reasons: Vec<String>,
+}
+
+impl Verdict {
+    pub fn is_clean(&self) -> bool {
+        self.level == VerdictLevel::Clean
+    }
+}
+
+/// Check the click times for the autoclickers and macros.
+///
+/// `synthetic` is how many synthetic key presses the input dropped, they are not in `clicks`.
+pub fn analyze_clicks(clicks: &[Duration], synthetic: usize) -> Verdict {
+    // every check gives the probability it is a machine
+    let mut checks: Vec<(f64, String)> = vec![];
+    let intervals = intervals(clicks);
+    if intervals.len() >= MIN_INTERVALS {
+        let mean = mean(&intervals);
+        let cv = if mean > 0.0 { stddev(&intervals) / mean } else { 0.0 };
+        if cv < MIN_HUMAN_CV {
+            checks.push((1.0 - cv / MIN_HUMAN_CV * 0.5, format!("Interval variance is too low (cv {:.4})", cv)));
+        }
+
+        let mut sorted = intervals.clone();
+        sorted.sort_by(f64::total_cmp);
+        let periodic = most_repeated(&sorted) as f64 / intervals.len() as f64;
+        if periodic > MAX_HUMAN_PERIODIC {
+            checks.push((periodic, format!("{:.0}% of the intervals are exactly the same", periodic * 100.0)));
+        }
+    }
+
+    let sustained = longest_above(clicks, MAX_HUMAN_CPS);
+    if sustained >= SUSTAIN {
+        checks.push((0.9, format!("Kept above {:.0} CPS for {:.1}s", MAX_HUMAN_CPS, sustained.as_secs_f64())));
+    }
+
+    if synthetic > 0 {
+        let ratio = synthetic as f64 / (clicks.len() + synthetic) as f64;
+        checks.push((0.3 + 0.7 * ratio, format!("{} synthetic key presses were ignored", synthetic)));
+    }
+
+    let confidence = 1.0 - checks.iter().map(|x| 1.0 - x.0.clamp(0.0, 1.0)).product::<f64>();
+    let level = if confidence >= 0.7 {
+        VerdictLevel::Macro
+    } else if confidence >= 0.3 {
+        VerdictLevel::Suspicious
+    } else {
+        VerdictLevel::Clean
+    };
+    Verdict {
+        level,
+        confidence,
+        reasons: checks.into_iter().map(|x| x.1).collect(),
+    }
+}
+
+/// The max count of the values within the tolerance, `xs` should be sorted
+fn most_repeated(xs: &[f64]) -> usize {
+    let mut best = 0;
+    let mut start = 0;
+    for i in 0..xs.len() {
+        while xs[i] - xs[start] > PERIOD_TOLERANCE {
+            start += 1;
+        }
+        best = best.max(i + 1 - start);
+    }
+    best
+}
+
+/// The longest time the rolling cps keeps above the value
+fn longest_above(clicks: &[Duration], cps: f64) -> Duration {
+    let mut best = Duration::ZERO;
+    let mut since = None;
+    for (t, x) in rolling_cps(clicks, Duration::from_secs(1)) {
+        if x > cps {
+            let since = *since.get_or_insert(t);
+            best = best.max(t - since);
+        } else {
+            since = None;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The clicks with the intervals of `mean` seconds plus up to `jitter` seconds either way
+    fn run(count: usize, mean: f64, jitter: f64) -> Vec<Duration> {
+        let mut seed = 0x2545_f491_u64;
+        let mut time = 0.0;
+        (0..count).map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let r = (seed >> 11) as f64 / (1u64 << 53) as f64;
+            time += mean + jitter * (r * 2.0 - 1.0);
+            Duration::from_secs_f64(time)
+        }).collect()
+    }
+
+    #[test]
+    fn fixed_interval_is_macro() {
+        let verdict = analyze_clicks(&run(100, 0.1, 0.0), 0);
+        assert_eq!(verdict.level, VerdictLevel::Macro);
+        assert_eq!(verdict.reasons.len(), 2);
+    }
+
+    #[test]
+    fn human_like_is_clean() {
+        let verdict = analyze_clicks(&run(100, 0.1, 0.02), 0);
+        assert_eq!(verdict.level, VerdictLevel::Clean, "{:?}", verdict);
+        assert_eq!(verdict.confidence, 0.0);
+        assert!(verdict.is_clean());
+    }
+
+    #[test]
+    fn synthetic_events() {
+        let clicks = run(100, 0.1, 0.02);
+        assert_eq!(analyze_clicks(&clicks, 1).level, VerdictLevel::Suspicious);
+        // most of the presses were synthetic
+        assert_eq!(analyze_clicks(&clicks, 400).level, VerdictLevel::Macro);
+    }
+
+    #[test]
+    fn sustained_speed_is_macro() {
+        let verdict = analyze_clicks(&run(200, 0.025, 0.005), 0);
+        assert_eq!(verdict.level, VerdictLevel::Macro, "{:?}", verdict);
+        assert_eq!(verdict.reasons.len(), 1);
+        // a short burst is possible
+        assert!(analyze_clicks(&run(60, 0.025, 0.005), 0).is_clean());
+    }
+
+    #[test]
+    fn too_few_intervals_are_not_judged() {
+        assert!(analyze_clicks(&run(MIN_INTERVALS, 0.1, 0.0), 0).is_clean());
+        assert!(analyze_clicks(&[], 0).is_clean());
+    }
+}
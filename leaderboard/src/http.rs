@@ -0,0 +1,99 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use anyhow::anyhow;
+
+/// Refuse the bodies larger than it
+const MAX_BODY: usize = 8 * 1024 * 1024;
+
+/// Refuse the start line and the headers larger than it in total
+const MAX_HEADER: u64 = 64 * 1024;
+
+/// A request or a response with the first line split by the spaces
+pub(crate) struct HttpMessage {
+    pub start: Vec<String>,
+    pub body: Vec<u8>,
+}
+
+/// Read one message with the content length, the connection is closed after it
+pub(crate) fn read_message(stream: &mut TcpStream) -> anyhow::Result<HttpMessage> {
+    let mut reader = BufReader::new(stream);
+    let mut header = (&mut reader).take(MAX_HEADER);
+    let mut line = String::new();
+    header.read_line(&mut line)?;
+    if header.limit() == 0 {
+        return Err(anyhow!("The header is larger than {} bytes", MAX_HEADER));
+    }
+    let start: Vec<String> = line.split_whitespace().map(String::from).collect();
+    if start.len() < 2 {
+        return Err(anyhow!("Bad http start line {:?}", line));
+    }
+    let mut len = 0;
+    loop {
+        line.clear();
+        let read = header.read_line(&mut line)?;
+        if header.limit() == 0 {
+            return Err(anyhow!("The header is larger than {} bytes", MAX_HEADER));
+        }
+        if read == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse()?;
+            }
+        }
+    }
+    if len > MAX_BODY {
+        return Err(anyhow!("The body of {} bytes is too large", len));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(HttpMessage { start, body })
+}
+
+/// Write the message with the start line, the body is json
+pub(crate) fn write_message(stream: &mut TcpStream, start: &str, host: Option<&str>, body: &[u8]) -> anyhow::Result<()> {
+    let mut data = format!("{}\r\n", start);
+    if let Some(host) = host {
+        data.push_str(&format!("Host: {}\r\n", host));
+    }
+    data.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()));
+    stream.write_all(data.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Percent encode for the path and the query
+pub(crate) fn encode(s: &str) -> String {
+    s.bytes().map(|x| match x {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (x as char).to_string(),
+        _ => format!("%{:02X}", x),
+    }).collect()
+}
+
+pub(crate) fn decode(s: &str) -> anyhow::Result<String> {
+    let bytes = s.as_bytes();
+    let mut ret = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3])?;
+                ret.push(u8::from_str_radix(hex, 16)?);
+                i += 3;
+            }
+            b'%' => return Err(anyhow!("Bad percent encoding {:?}", s)),
+            b'+' => {
+                ret.push(b' ');
+                i += 1;
+            }
+            x => {
+                ret.push(x);
+                i += 1;
+            }
+        }
+    }
+    Ok(String::from_utf8(ret)?)
+}
@@ -0,0 +1,12 @@
+pub use client::*;
+pub use detection::*;
+pub use score::*;
+pub use server::*;
+pub use stats::*;
+
+pub mod client;
+pub mod detection;
+mod http;
+pub mod score;
+pub mod server;
+pub mod stats;
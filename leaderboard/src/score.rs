@@ -0,0 +1,115 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{analyze_clicks, Verdict};
+
+/// The board of the tug of war players
+pub const TUG_BOARD: &str = "Tug of War";
+
+/// Refuse the runs with more clicks
+const MAX_CLICKS: usize = 100_000;
+const MAX_NAME_LEN: usize = 32;
+/// Refuse the longer runs, the intervals add up to the duration so they are bounded too
+const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+/// The intervals may add up over the duration by the rounding
+const DURATION_TOLERANCE: f64 = 0.001;
+
+/// A finished run posted to the leaderboard, the server computes the score from it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSubmission {
+    pub player: String,
+    /// The runs are ranked against the ones of the same board, the mode name
+    pub board: String,
+    /// From the go to the end of the run
+    pub duration: Duration,
+    /// Seconds between the clicks, the server checks the run by them
+    pub intervals: Vec<f64>,
+    /// How many synthetic key presses the input dropped, they are not in the intervals
+    pub synthetic: usize,
+}
+
+impl RunSubmission {
+    /// `None` if there are not enough clicks to rate
+    pub fn from_clicks(player: impl Into<String>, board: impl Into<String>, clicks: &[Duration], duration: Duration, synthetic: usize) -> Option<Self> {
+        if clicks.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            player: player.into(),
+            board: board.into(),
+            duration,
+            intervals: clicks.windows(2).map(|x| (x[1] - x[0]).as_secs_f64()).collect(),
+            synthetic,
+        })
+    }
+
+    pub fn clicks(&self) -> usize {
+        self.intervals.len() + 1
+    }
+
+    /// The score, the clicks per second over the duration
+    pub fn cps(&self) -> f64 {
+        let sec = self.duration.as_secs_f64();
+        if sec > 0.0 { self.clicks() as f64 / sec } else { 0.0 }
+    }
+
+    /// Check the run is possible and judge the intervals, the runs not clean are not ranked
+    pub fn verify(&self) -> anyhow::Result<Verdict> {
+        if self.player.trim().is_empty() || self.player.chars().count() > MAX_NAME_LEN {
+            return Err(anyhow!("The player name must be 1 to {} characters", MAX_NAME_LEN));
+        }
+        if self.board.trim().is_empty() || self.board.chars().count() > MAX_NAME_LEN {
+            return Err(anyhow!("The board name must be 1 to {} characters", MAX_NAME_LEN));
+        }
+        if self.intervals.is_empty() || self.clicks() > MAX_CLICKS {
+            return Err(anyhow!("The run must have 2 to {} clicks", MAX_CLICKS));
+        }
+        if self.intervals.iter().any(|x| !x.is_finite() || *x < 0.0) {
+            return Err(anyhow!("The intervals must be finite and not negative"));
+        }
+        if self.duration > MAX_DURATION {
+            return Err(anyhow!("The run must be shorter than {}s", MAX_DURATION.as_secs()));
+        }
+        if self.synthetic > MAX_CLICKS {
+            return Err(anyhow!("The run must have at most {} synthetic presses", MAX_CLICKS));
+        }
+        let total: f64 = self.intervals.iter().sum();
+        if total > self.duration.as_secs_f64() + DURATION_TOLERANCE {
+            return Err(anyhow!("The clicks take {:.3}s, longer than the run {:.3}s", total, self.duration.as_secs_f64()));
+        }
+        let mut time = Duration::ZERO;
+        let clicks: Vec<Duration> = std::iter::once(Duration::ZERO)
+            .chain(self.intervals.iter().map(|x| {
+                time += Duration::from_secs_f64(*x);
+                time
+            }))
+            .collect();
+        Ok(analyze_clicks(&clicks, self.synthetic))
+    }
+}
+
+/// The server answer to the submission
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmitResult {
+    /// From 1 in the board, none if the run is not ranked
+    pub rank: Option<usize>,
+    pub verdict: Verdict,
+}
+
+/// One row of the top table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub player: String,
+    pub cps: f64,
+    pub clicks: usize,
+    pub duration: Duration,
+    /// Unix seconds when the run was posted
+    pub timestamp: u64,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
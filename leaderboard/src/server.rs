@@ -0,0 +1,169 @@
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::http::{decode, read_message, write_message};
+use crate::{LeaderboardEntry, RunSubmission, SubmitResult, unix_now, Verdict};
+
+pub const DEFAULT_LEADERBOARD_PORT: u16 = 7881;
+
+/// Bump it when the store format changes
+pub const SCORES_VERSION: u32 = 1;
+
+/// The tables are at most this long
+const MAX_TOP: usize = 100;
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StoredRun {
+    run: RunSubmission,
+    timestamp: u64,
+    verdict: Verdict,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ScoresData {
+    version: u32,
+    runs: Vec<StoredRun>,
+}
+
+/// The runs of all boards, saved in the json file.
+pub struct ScoreStore {
+    data: ScoresData,
+    path: Option<PathBuf>,
+}
+
+impl ScoreStore {
+    pub fn in_memory() -> Self {
+        Self {
+            data: ScoresData { version: SCORES_VERSION, runs: vec![] },
+            path: None,
+        }
+    }
+
+    /// Load from the file, start empty if it does not exist
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let data = match std::fs::read(&path) {
+            Ok(data) => {
+                let data: ScoresData = serde_json::from_slice(&data)?;
+                if data.version != SCORES_VERSION {
+                    return Err(anyhow!("Unsupported scores version {}, expected {}", data.version, SCORES_VERSION));
+                }
+                data
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::in_memory().data,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { data, path: Some(path) })
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = serde_json::to_vec(&self.data).map_err(anyhow::Error::from).and_then(|data| Ok(std::fs::write(path, data)?)) {
+                log::warn!("Save the scores to {:?} failed for {:?}", path, e);
+            }
+        }
+    }
+
+    /// The clean runs of the board from the best
+    fn ranked<'a>(&'a self, board: &'a str) -> Vec<&'a StoredRun> {
+        let mut ret: Vec<_> = self.data.runs.iter().filter(|x| x.run.board == board && x.verdict.is_clean()).collect();
+        ret.sort_by(|a, b| b.run.cps().total_cmp(&a.run.cps()).then(a.timestamp.cmp(&b.timestamp)));
+        ret
+    }
+
+    /// Keep the run, it is ranked if the intervals look human
+    pub fn submit(&mut self, run: RunSubmission) -> anyhow::Result<SubmitResult> {
+        let verdict = run.verify()?;
+        let stored = StoredRun { run, timestamp: unix_now(), verdict: verdict.clone() };
+        self.data.runs.push(stored.clone());
+        self.save();
+        let rank = self.ranked(&stored.run.board).iter().position(|x| **x == stored).map(|x| x + 1);
+        Ok(SubmitResult { rank, verdict })
+    }
+
+    pub fn boards(&self) -> Vec<String> {
+        self.data.runs.iter().filter(|x| x.verdict.is_clean()).map(|x| x.run.board.clone()).collect::<BTreeSet<_>>().into_iter().collect()
+    }
+
+    pub fn top(&self, board: &str, count: usize) -> Vec<LeaderboardEntry> {
+        self.ranked(board).into_iter().take(count.min(MAX_TOP)).enumerate().map(|(i, x)| LeaderboardEntry {
+            rank: i + 1,
+            player: x.run.player.clone(),
+            cps: x.run.cps(),
+            clicks: x.run.clicks(),
+            duration: x.run.duration,
+            timestamp: x.timestamp,
+        }).collect()
+    }
+}
+
+/// Serves the [`ScoreStore`] over http for the [`crate::HttpBackend`].
+///
+/// `POST /runs`, `GET /boards` and `GET /boards/{board}/top?count=N`, all json.
+pub struct LeaderboardServer {
+    listener: TcpListener,
+    store: ScoreStore,
+}
+
+impl LeaderboardServer {
+    pub fn bind(addr: impl Into<SocketAddr>, store: ScoreStore) -> anyhow::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr.into())?,
+            store,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Serve the clients one by one forever
+    pub fn run(mut self) {
+        log::info!("Serving the leaderboard on {:?}", self.local_addr());
+        loop {
+            match self.listener.accept() {
+                Ok((mut stream, addr)) => {
+                    if let Err(e) = self.serve(&mut stream) {
+                        log::warn!("Serve {} failed for {:?}", addr, e);
+                    }
+                }
+                Err(e) => log::warn!("Accept failed for {:?}", e),
+            }
+        }
+    }
+
+    fn serve(&mut self, stream: &mut TcpStream) -> anyhow::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let request = read_message(stream)?;
+        let (status, body) = match self.route(&request.start[0], &request.start[1], &request.body) {
+            Ok(body) => ("200 OK", body),
+            Err(e) => ("400 Bad Request", serde_json::to_vec(&e.to_string())?),
+        };
+        write_message(stream, &format!("HTTP/1.1 {}", status), None, &body)
+    }
+
+    fn route(&mut self, method: &str, target: &str, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+        Ok(match (method, &segments[..]) {
+            ("POST", ["runs"]) => serde_json::to_vec(&self.store.submit(serde_json::from_slice(body)?)?)?,
+            ("GET", ["boards"]) => serde_json::to_vec(&self.store.boards())?,
+            ("GET", ["boards", board, "top"]) => {
+                let count = query.split('&')
+                    .find_map(|x| x.strip_prefix("count="))
+                    .map(|x| x.parse())
+                    .transpose()?
+                    .unwrap_or(10);
+                serde_json::to_vec(&self.store.top(&decode(board)?, count))?
+            }
+            _ => return Err(anyhow!("No route for {} {}", method, path)),
+        })
+    }
+}
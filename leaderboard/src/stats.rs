@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+/// The percentiles of the intervals in [`ClickStats::percentiles`]
+pub const PERCENTILES: [f64; 4] = [0.1, 0.5, 0.9, 0.99];
+
+/// The window for [`ClickStats::max_rolling_cps`]
+pub const ROLLING_WINDOW: Duration = Duration::from_secs(1);
+
+/// The statistics over the click times.
+///
+/// The bpm is `15 * cps` as the click test shows, one click is a 1/16 note.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClickStats {
+    pub clicks: usize,
+    /// Seconds from the first click to the last one
+    pub duration: f64,
+    /// Seconds between the clicks
+    pub mean_interval: f64,
+    pub stddev: f64,
+    /// The interval stddev in milliseconds times 10, as rhythm games define it
+    pub unstable_rate: f64,
+    /// The interval seconds at [`PERCENTILES`]
+    pub percentiles: [f64; 4],
+    pub max_rolling_cps: f64,
+    pub target_bpm: f64,
+    /// The longest run of clicks with every interval at or above the target bpm
+    pub streak: Streak,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Streak {
+    pub clicks: usize,
+    pub duration: Duration,
+}
+
+impl ClickStats {
+    /// Compute all the stats, the clicks should be sorted.
+    pub fn from_clicks(clicks: &[Duration], target_bpm: f64) -> Self {
+        let intervals = intervals(clicks);
+        let mut sorted = intervals.clone();
+        sorted.sort_by(f64::total_cmp);
+        let stddev = stddev(&intervals);
+        Self {
+            clicks: clicks.len(),
+            duration: match (clicks.first(), clicks.last()) {
+                (Some(first), Some(last)) => (*last - *first).as_secs_f64(),
+                _ => 0.0,
+            },
+            mean_interval: mean(&intervals),
+            stddev,
+            unstable_rate: unstable_rate(stddev),
+            percentiles: PERCENTILES.map(|p| percentile(&sorted, p)),
+            max_rolling_cps: rolling_cps(clicks, ROLLING_WINDOW).into_iter().map(|x| x.1).fold(0.0, f64::max),
+            target_bpm,
+            streak: longest_streak(clicks, target_bpm),
+        }
+    }
+
+    /// The cps from the mean interval
+    pub fn mean_cps(&self) -> f64 {
+        if self.mean_interval > 0.0 { 1.0 / self.mean_interval } else { 0.0 }
+    }
+}
+
+/// Get the seconds between the clicks
+pub fn intervals(clicks: &[Duration]) -> Vec<f64> {
+    clicks.windows(2).map(|x| x[1].saturating_sub(x[0]).as_secs_f64()).collect()
+}
+
+pub fn mean(xs: &[f64]) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+/// The population standard deviation
+pub fn stddev(xs: &[f64]) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    let mean = mean(xs);
+    (xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / xs.len() as f64).sqrt()
+}
+
+/// Get the unstable rate from the stddev in seconds
+pub fn unstable_rate(stddev: f64) -> f64 {
+    stddev * 1000.0 * 10.0
+}
+
+/// Get the percentile in `[0, 1]` by linear interpolation, `xs` should be sorted.
+pub fn percentile(xs: &[f64], p: f64) -> f64 {
+    match xs.len() {
+        0 => 0.0,
+        1 => xs[0],
+        len => {
+            let rank = p.clamp(0.0, 1.0) * (len - 1) as f64;
+            let lower = rank.floor() as usize;
+            let upper = rank.ceil() as usize;
+            xs[lower] + (xs[upper] - xs[lower]) * (rank - lower as f64)
+        }
+    }
+}
+
+/// Get the cps in the window ending at every click
+pub fn rolling_cps(clicks: &[Duration], window: Duration) -> Vec<(Duration, f64)> {
+    let sec = window.as_secs_f64();
+    let mut start = 0;
+    clicks.iter().enumerate().map(|(i, &t)| {
+        while t.saturating_sub(clicks[start]) >= window {
+            start += 1;
+        }
+        (t, (i + 1 - start) as f64 / sec)
+    }).collect()
+}
+
+/// Get the cps in the window ending at the time
+pub fn cps_at(clicks: &[Duration], end: Duration, window: Duration) -> f64 {
+    let count = clicks.iter().rev().skip_while(|t| **t > end).take_while(|t| end.saturating_sub(**t) < window).count();
+    count as f64 / window.as_secs_f64()
+}
+
+/// Count the values into the bins from zero, the bin `i` is `[i * width, (i + 1) * width)`.
+///
+/// The values at or above `max` are not counted.
+pub fn histogram(xs: &[f64], width: f64, max: f64) -> Vec<usize> {
+    let len = (max / width).ceil() as usize;
+    let mut bins = vec![0; len];
+    for x in xs {
+        if *x >= 0.0 && *x < max {
+            bins[((x / width) as usize).min(len - 1)] += 1;
+        }
+    }
+    while bins.last() == Some(&0) {
+        bins.pop();
+    }
+    bins
+}
+
+/// The longest run of clicks with every interval short enough for the bpm
+pub fn longest_streak(clicks: &[Duration], bpm: f64) -> Streak {
+    if clicks.is_empty() || bpm <= 0.0 {
+        return Streak::default();
+    }
+    let max_interval = Duration::from_secs_f64(15.0 / bpm);
+    let mut best = Streak { clicks: 1, duration: Duration::ZERO };
+    let mut start = 0;
+    for i in 1..clicks.len() {
+        if clicks[i].saturating_sub(clicks[i - 1]) > max_interval {
+            start = i;
+        }
+        if i + 1 - start > best.clicks {
+            best = Streak {
+                clicks: i + 1 - start,
+                duration: clicks[i] - clicks[start],
+            };
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(xs: &[u64]) -> Vec<Duration> {
+        xs.iter().map(|x| Duration::from_millis(*x)).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn intervals_mean_and_stddev() {
+        let stats = ClickStats::from_clicks(&ms(&[0, 100, 300, 600, 1000]), 0.0);
+        assert_eq!(stats.clicks, 5);
+        assert!(close(stats.duration, 1.0));
+        // the intervals are 0.1, 0.2, 0.3 and 0.4
+        assert!(close(stats.mean_interval, 0.25));
+        assert!(close(stats.stddev, 0.0125f64.sqrt()));
+        assert!(close(stats.unstable_rate, stats.stddev * 10000.0));
+        assert!(close(stats.mean_cps(), 4.0));
+    }
+
+    #[test]
+    fn percentiles_interpolate() {
+        let stats = ClickStats::from_clicks(&ms(&[0, 100, 300, 600, 1000]), 0.0);
+        assert!(close(stats.percentiles[0], 0.13));
+        assert!(close(stats.percentiles[1], 0.25));
+        assert!(close(stats.percentiles[2], 0.37));
+        assert!(close(stats.percentiles[3], 0.397));
+        assert_eq!(percentile(&[], 0.5), 0.0);
+        assert_eq!(percentile(&[0.2], 0.9), 0.2);
+    }
+
+    #[test]
+    fn streak_at_target_bpm() {
+        // 1/16 notes at 150 bpm are 0.1s apart
+        let stats = ClickStats::from_clicks(&ms(&[0, 100, 200, 300, 500, 600, 700, 800, 900, 1200]), 150.0);
+        assert_eq!(stats.streak, Streak { clicks: 5, duration: Duration::from_millis(400) });
+        assert_eq!(longest_streak(&ms(&[0, 100, 200]), 0.0), Streak::default());
+        assert_eq!(longest_streak(&ms(&[0, 500, 1000]), 150.0), Streak { clicks: 1, duration: Duration::ZERO });
+    }
+
+    #[test]
+    fn empty_and_single_click() {
+        assert_eq!(ClickStats::from_clicks(&[], 150.0), ClickStats { target_bpm: 150.0, ..Default::default() });
+        let single = ClickStats::from_clicks(&ms(&[250]), 150.0);
+        assert_eq!(single.clicks, 1);
+        assert_eq!(single.duration, 0.0);
+        assert_eq!(single.mean_interval, 0.0);
+        assert_eq!(single.stddev, 0.0);
+        assert_eq!(single.percentiles, [0.0; 4]);
+        assert_eq!(single.max_rolling_cps, 1.0);
+        assert_eq!(single.mean_cps(), 0.0);
+        assert_eq!(single.streak, Streak { clicks: 1, duration: Duration::ZERO });
+    }
+
+    #[test]
+    fn rolling_window() {
+        let clicks = ms(&[0, 100, 200, 1000, 1100]);
+        let cps: Vec<f64> = rolling_cps(&clicks, ROLLING_WINDOW).into_iter().map(|x| x.1).collect();
+        assert_eq!(cps, vec![1.0, 2.0, 3.0, 3.0, 3.0]);
+        assert_eq!(cps_at(&clicks, Duration::from_millis(1150), ROLLING_WINDOW), 3.0);
+        assert_eq!(histogram(&[0.01, 0.015, 0.03, 0.5], 0.01, 0.1), vec![0, 2, 0, 1]);
+    }
+}
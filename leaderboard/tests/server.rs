@@ -0,0 +1,145 @@
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::time::Duration;
+
+use andy_clicker_leaderboard::*;
+
+/// Human-like clicks, the intervals jitter between 80ms and 160ms
+fn human(count: usize, mut seed: u64) -> Vec<Duration> {
+    let mut time = 0.1;
+    (0..count).map(|_| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        time += 0.08 + 0.08 * ((seed >> 11) as f64 / (1u64 << 53) as f64);
+        Duration::from_secs_f64(time)
+    }).collect()
+}
+
+fn run(player: &str, board: &str, clicks: &[Duration], duration: Duration) -> RunSubmission {
+    RunSubmission::from_clicks(player, board, clicks, duration, 0).unwrap()
+}
+
+fn serve(store: ScoreStore) -> HttpBackend {
+    let server = LeaderboardServer::bind((Ipv4Addr::LOCALHOST, 0), store).unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+    HttpBackend::new(addr.to_string())
+}
+
+#[test]
+fn ranks_the_runs() {
+    let backend = serve(ScoreStore::in_memory());
+    let ten = Duration::from_secs(10);
+    assert!(backend.boards().unwrap().is_empty());
+
+    let first = backend.submit(&run("alice", "Timed 10s", &human(60, 1), ten)).unwrap();
+    assert_eq!(first.rank, Some(1));
+    assert!(first.verdict.is_clean());
+    assert_eq!(backend.submit(&run("bob", "Timed 10s", &human(80, 2), ten)).unwrap().rank, Some(1));
+    assert_eq!(backend.submit(&run("carol", "Timed 10s", &human(40, 3), ten)).unwrap().rank, Some(3));
+
+    let top = backend.top("Timed 10s", 10).unwrap();
+    assert_eq!(top.iter().map(|x| x.player.as_str()).collect::<Vec<_>>(), ["bob", "alice", "carol"]);
+    assert_eq!(top.iter().map(|x| x.rank).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(top[0].clicks, 80);
+    assert_eq!(top[0].cps, 8.0);
+    assert_eq!(top[0].duration, ten);
+    assert_eq!(backend.top("Timed 10s", 2).unwrap().len(), 2);
+    assert!(backend.top("Nobody", 10).unwrap().is_empty());
+}
+
+#[test]
+fn rejects_the_bad_runs() {
+    let backend = serve(ScoreStore::in_memory());
+    let clicks = human(60, 1);
+
+    // kept but not ranked
+    let periodic: Vec<Duration> = (0..100).map(|i| Duration::from_millis(50 * i)).collect();
+    let result = backend.submit(&run("bot", "Timed 10s", &periodic, Duration::from_secs(10))).unwrap();
+    assert_eq!(result.rank, None);
+    assert_eq!(result.verdict.level, VerdictLevel::Macro);
+    let synthetic = RunSubmission::from_clicks("keys", "Timed 10s", &clicks, Duration::from_secs(10), 60).unwrap();
+    assert_eq!(backend.submit(&synthetic).unwrap().rank, None);
+    assert!(backend.top("Timed 10s", 10).unwrap().is_empty());
+    assert!(backend.boards().unwrap().is_empty());
+
+    // impossible
+    assert!(backend.submit(&run("liar", "Timed 10s", &clicks, Duration::from_secs(1))).is_err());
+    assert!(backend.submit(&run(" ", "Timed 10s", &clicks, Duration::from_secs(10))).is_err());
+    assert!(backend.submit(&run("alice", "", &clicks, Duration::from_secs(10))).is_err());
+    let mut negative = run("alice", "Timed 10s", &clicks, Duration::from_secs(10));
+    negative.intervals[3] = -0.1;
+    assert!(backend.submit(&negative).is_err());
+    let mut more_synthetic = run("alice", "Timed 10s", &clicks, Duration::from_secs(10));
+    more_synthetic.synthetic = 100_001;
+    assert!(backend.submit(&more_synthetic).is_err());
+    let mut endless = run("alice", "Timed 10s", &clicks, Duration::from_secs(u64::MAX));
+    endless.intervals[0] = 1.8446744073709552e19;
+    assert!(backend.submit(&endless).is_err());
+    // the server is still up
+    assert!(backend.boards().unwrap().is_empty());
+}
+
+#[test]
+fn refuses_the_endless_header() {
+    let server = LeaderboardServer::bind((Ipv4Addr::LOCALHOST, 0), ScoreStore::in_memory()).unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+    let mut stream = TcpStream::connect(addr).unwrap();
+    // the server drops it before its read timeout
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let line = format!("GET /boards HTTP/1.1\r\nX-Long: {}", "a".repeat(100 * 1024));
+    let _ = stream.write_all(line.as_bytes());
+    let mut rest = vec![];
+    match stream.read_to_end(&mut rest) {
+        Ok(_) => assert!(rest.is_empty()),
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+    }
+    assert!(HttpBackend::new(addr.to_string()).boards().unwrap().is_empty());
+}
+
+#[test]
+fn encodes_the_board_names() {
+    let backend = serve(ScoreStore::in_memory());
+    let boards = [TUG_BOARD, "50% / fast?", "Count+50 &more", "タイム"];
+    for (i, board) in boards.iter().enumerate() {
+        assert_eq!(backend.submit(&run("alice", board, &human(50, i as u64), Duration::from_secs(10))).unwrap().rank, Some(1));
+    }
+    let mut sorted = boards.map(String::from).to_vec();
+    sorted.sort();
+    assert_eq!(backend.boards().unwrap(), sorted);
+    for board in boards {
+        let top = backend.top(board, 10).unwrap();
+        assert_eq!(top.len(), 1, "{}", board);
+    }
+}
+
+#[test]
+fn submits_in_background_and_persists() {
+    let path = std::env::temp_dir().join(format!("andy-scores-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let backend = serve(ScoreStore::load(path.clone()).unwrap());
+    let leaderboard = Leaderboard::new(backend);
+    assert!(leaderboard.is_enabled());
+    assert!(!Leaderboard::http("  ").is_enabled());
+
+    leaderboard.submit(run("dave", "Count 50", &human(50, 3), Duration::from_secs(8)));
+    let top = loop {
+        let pending = leaderboard.top("Count 50", 5);
+        let top = loop {
+            match pending.try_take() {
+                Some(x) => break x.unwrap(),
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        if !top.is_empty() {
+            break top;
+        }
+    };
+    assert_eq!(top[0].player, "dave");
+
+    let store = ScoreStore::load(path.clone()).unwrap();
+    assert_eq!(store.top("Count 50", 10)[0].player, "dave");
+    std::fs::write(&path, b"{").unwrap();
+    assert!(ScoreStore::load(path.clone()).is_err());
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,31 @@
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+use log::LevelFilter;
+
+use andy_clicker_leaderboard::{DEFAULT_LEADERBOARD_PORT, LeaderboardServer, ScoreStore};
+
+/// `leaderboard_server [port] [scores file]`, the scores are saved to `scores.json` by default
+fn main() {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .parse_default_env()
+        .init();
+    let mut args = std::env::args().skip(1);
+    let port = args.next().map(|x| x.parse().expect("The port must be a number")).unwrap_or(DEFAULT_LEADERBOARD_PORT);
+    let path = PathBuf::from(args.next().unwrap_or_else(|| "scores.json".into()));
+    let store = match ScoreStore::load(path.clone()) {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Load the scores {:?} failed for {:?}", path, e);
+            std::process::exit(1);
+        }
+    };
+    match LeaderboardServer::bind((Ipv4Addr::UNSPECIFIED, port), store) {
+        Ok(server) => server.run(),
+        Err(e) => {
+            log::error!("Listen on port {} failed for {:?}", port, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,24 +1,31 @@
 use std::collections::HashSet;
 use std::default::Default;
+use std::time::Duration;
 
-use egui::Context;
+use egui::{Context, FullOutput, RawInput};
 use egui_wgpu::renderer::ScreenDescriptor;
 use egui_winit::State;
 use log::{info, warn};
 use specs::{World, WorldExt};
 use wgpu::{Color, CommandEncoderDescriptor, Extent3d, ImageCopyTexture, LoadOp,
            Operations, Origin3d, RenderPassColorAttachment, RenderPassDescriptor, TextureAspect};
-use winit::event::{ElementState, Event, WindowEvent};
+use winit::dpi::PhysicalPosition;
+use winit::event::{Event, MouseButton, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::Window;
 
-use crate::engine::{AudioData, BakedInputs, GameState, LoopState, MainRendererData, MainRenderViews, Pointer, ResourcesHandles, StateEvent, Trans, WgpuData};
+use crate::engine::{AudioData, BakedInputs, Clock, EguiEvents, FixedTimestep, GameState, InputEventKind, InputRecorder, InputReplay, Leaderboard, LoopState, SessionHistory, MainRendererData, MainRenderViews, MonotonicClock, ResourcesHandles, Settings, StateEvent, Trans, WgpuData};
 
 pub struct WindowInstance {
-    pub window: Window,
+    /// `None` when running headless
+    pub window: Option<Window>,
     pub gpu: Option<WgpuData>,
     pub render: Option<MainRendererData>,
     pub res: ResourcesHandles,
+    pub settings: Settings,
+    pub history: SessionHistory,
+    /// Where the finished runs are posted
+    pub leaderboard: Leaderboard,
     pub last_render_time: std::time::Instant,
     pub egui_ctx: Context,
     pub egui_state: State,
@@ -28,6 +35,8 @@ pub struct WindowInstance {
     pub world: World,
 
     pub audio: Option<AudioData>,
+    pub clock: Box<dyn Clock>,
+    pub timestep: FixedTimestep,
 }
 
 impl WindowInstance {
@@ -63,28 +72,73 @@ impl WindowInstance {
             }
         };
 
+        let size = window.inner_size();
+        let settings = Settings::load_default();
+        let leaderboard = Leaderboard::http(&settings.get().leaderboard_address);
         info!("Almost got all window instance field");
         Self {
-            window,
+            window: Some(window),
             gpu,
             render,
             res,
+            settings,
+            history: SessionHistory::load_default(),
+            leaderboard,
             last_render_time: std::time::Instant::now(),
             egui_ctx,
             egui_state: State::new(event_loop),
-            inputs: Default::default(),
+            inputs: BakedInputs::new([size.width as f32 / 1600.0, size.height as f32 / 900.0]),
             lua: rua,
             world: World::new(),
             audio: al,
+            clock: Box::new(MonotonicClock::default()),
+            timestep: FixedTimestep::default(),
+        }
+    }
+
+    /// Create the instance without window, gpu and audio.
+    pub fn headless() -> Self {
+        Self {
+            window: None,
+            gpu: None,
+            render: None,
+            res: ResourcesHandles::default(),
+            settings: Settings::in_memory(),
+            history: SessionHistory::in_memory(),
+            leaderboard: Default::default(),
+            last_render_time: std::time::Instant::now(),
+            egui_ctx: Context::default(),
+            egui_state: State::new_with_wayland_display(None),
+            inputs: BakedInputs::new([1.0, 1.0]),
+            lua: mlua::Lua::new(),
+            world: World::new(),
+            audio: None,
+            clock: Box::new(MonotonicClock::default()),
+            timestep: FixedTimestep::default(),
         }
     }
 }
 
 
+/// The inputs waiting to be baked at the end of the events
+#[derive(Default)]
+struct PendingInputs {
+    pressed_keys: HashSet<VirtualKeyCode>,
+    released_keys: HashSet<VirtualKeyCode>,
+    pressed_buttons: HashSet<MouseButton>,
+    released_buttons: HashSet<MouseButton>,
+}
+
 pub struct Application {
-    window: WindowInstance,
-    states: Vec<Box<dyn GameState>>,
-    running: bool,
+    pub(in crate::engine) window: WindowInstance,
+    pub(in crate::engine) states: Vec<Box<dyn GameState>>,
+    pub(in crate::engine) running: bool,
+    pending: PendingInputs,
+    pub(in crate::engine) recorder: Option<InputRecorder>,
+    /// Feed the recorded inputs instead of the window inputs
+    replay: Option<InputReplay>,
+    /// The replayed inputs for the next ui pass
+    replay_egui: EguiEvents,
 }
 
 macro_rules! get_state {
@@ -96,22 +150,102 @@ macro_rules! get_state {
 
 impl Application {
     pub fn new(window: Window, event_loop: &EventLoop<()>) -> Self {
-        Self { window: WindowInstance::new(window, event_loop), states: vec![], running: true }
+        Self { window: WindowInstance::new(window, event_loop), states: vec![], running: true, pending: Default::default(), recorder: None, replay: None, replay_egui: Default::default() }
+    }
+
+    pub fn headless() -> Self {
+        Self { window: WindowInstance::headless(), states: vec![], running: true, pending: Default::default(), recorder: None, replay: None, replay_egui: Default::default() }
+    }
+
+    /// Record the frames and their inputs, the recorder saves when the loop exits.
+    pub fn with_recorder(mut self, recorder: InputRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Replay the recorded frames on the replay clock, the window inputs are ignored then.
+    pub fn with_replay(mut self, replay: InputReplay) -> Self {
+        self.window.clock = Box::new(replay.clock());
+        self.replay = Some(replay);
+        self
+    }
+
+    pub(in crate::engine) fn handle_input(&mut self, kind: InputEventKind) {
+        let now = self.window.clock.now();
+        self.handle_input_at(now, kind);
+    }
+
+    /// Record the input event happened at the time and apply it to the baked inputs.
+    pub(in crate::engine) fn handle_input_at(&mut self, now: Duration, kind: InputEventKind) {
+        let inputs = &mut self.window.inputs;
+        inputs.push_event(now, kind);
+        let pending = &mut self.pending;
+        match kind {
+            InputEventKind::Key { key, pressed, synthetic: false, .. } => {
+                if pressed {
+                    pending.pressed_keys.insert(key);
+                } else {
+                    pending.released_keys.insert(key);
+                }
+            }
+            InputEventKind::Key { .. } => {}
+            InputEventKind::Mouse { button, pressed } => {
+                if pressed {
+                    pending.pressed_buttons.insert(button);
+                } else {
+                    pending.released_buttons.insert(button);
+                }
+            }
+            InputEventKind::Touch { id, phase, x, y } => {
+                inputs.process_touch(id, phase, PhysicalPosition::new(x, y), now);
+            }
+            InputEventKind::CursorMoved { x, y } => inputs.set_cursor(Some(PhysicalPosition::new(x, y))),
+            InputEventKind::CursorLeft => inputs.set_cursor(None),
+            InputEventKind::Wheel { x, y } => inputs.add_wheel(x, y),
+        }
     }
 
-    fn loop_once(&mut self) -> LoopState {
+    /// Bake the pending keys and buttons into the inputs, the real loop does it at `MainEventsCleared`.
+    pub(in crate::engine) fn bake_inputs(&mut self) {
+        let pending = &mut self.pending;
+        if !pending.pressed_keys.is_empty() || !pending.released_keys.is_empty() {
+            log::trace!(target: "InputTrace", "process pressed_key {:?} and released {:?}", pending.pressed_keys, pending.released_keys);
+            self.window.inputs.process(&pending.pressed_keys, &pending.released_keys);
+            pending.pressed_keys.clear();
+            pending.released_keys.clear();
+        }
+        if !pending.pressed_buttons.is_empty() || !pending.released_buttons.is_empty() {
+            log::trace!(target: "InputTrace", "process pressed_button {:?} and released {:?}", pending.pressed_buttons, pending.released_buttons);
+            self.window.inputs.process_mouse(&pending.pressed_buttons, &pending.released_buttons);
+            pending.pressed_buttons.clear();
+            pending.released_buttons.clear();
+        }
+    }
+
+    pub(in crate::engine) fn loop_once(&mut self) -> LoopState {
         profiling::scope!("Loop logic once");
         let mut loop_result = LoopState::WAIT_ALL;
 
 
+        self.process_settings();
         self.window.inputs.swap_frame();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_frame(self.window.clock.now(), self.window.inputs.frame_events());
+        }
         {
             let mut state_data = get_state!(self);
 
             for x in &mut self.states {
                 loop_result |= x.shadow_update();
             }
+            let now = state_data.now();
+            let ticks = state_data.window.timestep.advance(now);
             if let Some(last) = self.states.last_mut() {
+                state_data.dt = state_data.window.timestep.dt();
+                for _ in 0..ticks {
+                    last.fixed_update(&mut state_data);
+                }
+                state_data.dt = 0.0;
                 let (tran, l) = last.update(&mut state_data);
                 self.process_tran(tran);
                 loop_result |= l;
@@ -121,7 +255,18 @@ impl Application {
         loop_result
     }
 
-    fn process_tran(&mut self, tran: Trans) {
+    /// Apply the changed settings, tell the states and save them.
+    fn process_settings(&mut self) {
+        if self.window.settings.take_changed() {
+            self.window.inputs.bindings = self.window.settings.get().bindings.clone();
+            let mut sd = get_state!(self);
+            self.states.iter_mut().for_each(|x| x.on_event(Some(&mut sd), StateEvent::SettingsChanged));
+        }
+        let now = self.window.clock.now();
+        self.window.settings.save_if_dirty(now);
+    }
+
+    pub(in crate::engine) fn process_tran(&mut self, tran: Trans) {
         let last = self.states.last_mut().unwrap();
         let mut state_data = get_state!(self);
 
@@ -134,8 +279,9 @@ impl Application {
                 last.stop(&mut state_data);
                 self.states.pop().unwrap();
             }
-            Trans::Switch(x) => {
+            Trans::Switch(mut x) => {
                 last.stop(&mut state_data);
+                x.start(&mut state_data);
                 *last = x;
             }
             Trans::Exit => {
@@ -153,12 +299,39 @@ impl Application {
         }
     }
 
+    /// Run the egui pass for all states and process the transition of the top state.
+    pub(in crate::engine) fn run_ui(&mut self, raw_input: RawInput, dt: f32) -> FullOutput {
+        let egui_ctx = &self.window.egui_ctx.clone();
+        egui_ctx.run(raw_input, |egui_ctx| {
+            {
+                let mut state_data = get_state!(self);
+                state_data.dt = dt;
+
+
+                for game_state in &mut self.states {
+                    game_state.shadow_render(&state_data, egui_ctx);
+                }
+                if let Some(g) = self.states.last_mut() {
+                    let tran = g.render(&mut state_data, egui_ctx);
+                    self.process_tran(tran);
+                }
+            }
+        })
+    }
+
+    pub(in crate::engine) fn post_ui_render(&mut self, dt: f32) {
+        let mut sd = get_state!(self);
+        sd.dt = dt;
+        self.states.iter_mut().for_each(|s| s.on_event(Some(&mut sd), StateEvent::PostUiRender));
+    }
+
     fn render_once(&mut self) {
-        if let (Some(gpu), Some(render)) = (&self.window.gpu, &mut self.window.render) {
+        if let (Some(window), Some(gpu), Some(render)) = (&self.window.window, &self.window.gpu, &mut self.window.render) {
             profiling::scope!("Render pth once");
             let render_now = std::time::Instant::now();
             let render_dur = render_now.duration_since(self.window.last_render_time);
             let dt = render_dur.as_secs_f32();
+            let scale_factor = window.scale_factor() as f32;
 
             let swap_chain_frame
                 = if let Ok(s) = gpu.surface.get_current_texture() { s } else { return; };
@@ -185,22 +358,9 @@ impl Application {
                 gpu.queue.submit(Some(encoder.finish()));
             }
 
-            let egui_ctx = &self.window.egui_ctx.clone();
-            let full_output = egui_ctx.run(self.window.egui_state.take_egui_input(&self.window.window), |egui_ctx| {
-                {
-                    let mut state_data = get_state!(self);
-                    state_data.dt = dt;
-
-
-                    for game_state in &mut self.states {
-                        game_state.shadow_render(&state_data, egui_ctx);
-                    }
-                    if let Some(g) = self.states.last_mut() {
-                        let tran = g.render(&mut state_data, egui_ctx);
-                        self.process_tran(tran);
-                    }
-                }
-            });
+            let mut raw_input = self.window.egui_state.take_egui_input(window);
+            raw_input.events.extend(self.replay_egui.take());
+            let full_output = self.run_ui(raw_input, dt);
             let gpu = self.window.gpu.as_ref().unwrap();
             let render = self.window.render.as_mut().unwrap();
             // render ui output to main screen
@@ -213,7 +373,7 @@ impl Application {
 
                 let screen_descriptor = ScreenDescriptor {
                     size_in_pixels: [gpu.surface_cfg.width, gpu.surface_cfg.height],
-                    pixels_per_point: self.window.window.scale_factor() as f32,
+                    pixels_per_point: scale_factor,
                 };
                 // Upload all resources for the GPU.
 
@@ -247,11 +407,7 @@ impl Application {
                 queue.submit(std::iter::once(encoder.finish()));
                 full_output.textures_delta.free.iter().for_each(|id| egui_renderer.free_texture(id));
             }
-            {
-                let mut sd = get_state!(self);
-                sd.dt = dt;
-                self.states.iter_mut().for_each(|s| s.on_event(Some(&mut sd), StateEvent::PostUiRender));
-            }
+            self.post_ui_render(dt);
             let gpu = self.window.gpu.as_ref().unwrap();
             let render = self.window.render.as_mut().unwrap();
 
@@ -285,7 +441,9 @@ impl Application {
             // self.window.pools.render_pool.try_run_one();
             self.window.last_render_time = render_now;
             swap_chain_frame.present();
-            self.window.egui_state.handle_platform_output(&self.window.window, &self.window.egui_ctx, full_output.platform_output);
+            if let Some(window) = &self.window.window {
+                self.window.egui_state.handle_platform_output(window, &self.window.egui_ctx, full_output.platform_output);
+            }
         }
     }
 
@@ -294,11 +452,16 @@ impl Application {
         info!("Started the start state.");
         self.states.push(Box::new(start));
         let mut game_draw_requested = false;
-        let mut pressed_keys = HashSet::new();
-        let mut released_keys = HashSet::new();
         event_loop.run(move |event, _, control_flow| {
             if let Event::WindowEvent { event, .. } = &event {
-                let _ = self.window.egui_state.on_event(&self.window.egui_ctx, event);
+                let input = InputEventKind::from_window_event(event);
+                if let (Some(kind), None) = (input, &self.replay) {
+                    self.handle_input(kind);
+                }
+                // the replay feeds the recorded inputs to egui instead
+                if input.is_none() || self.replay.is_none() {
+                    let _ = self.window.egui_state.on_event(&self.window.egui_ctx, event);
+                }
                 for x in &mut self.states {
                     x.on_event(None, StateEvent::Window(event));
                 }
@@ -326,17 +489,18 @@ impl Application {
                     }
                 }
                 Event::Resumed => {
-                    if self.window.gpu.is_none() {
+                    if let (None, Some(window)) = (&self.window.gpu, &self.window.window) {
                         info!("gpu not found, try to init");
-                        self.window.gpu = WgpuData::new(&self.window.window).ok();
+                        self.window.gpu = WgpuData::new(window).ok();
                         if let Some(gpu) = &self.window.gpu {
                             self.window.render = Some(MainRendererData::new(gpu, &self.window.res));
                             let mut sd = get_state!(self);
                             self.states.iter_mut().for_each(|x| x.on_event(Some(&mut sd), StateEvent::FoundGPU));
                         }
                         self.window.egui_ctx = Context::default();
-                        let size = self.window.window.inner_size();
-                        self.window.egui_ctx.set_pixels_per_point(self.window.window.scale_factor() as f32);
+                        let window = self.window.window.as_ref().unwrap();
+                        let size = window.inner_size();
+                        self.window.egui_ctx.set_pixels_per_point(window.scale_factor() as f32);
                         let _ = self.window.egui_state.on_event(&self.window.egui_ctx, &WindowEvent::Resized(size));
                     }
                 }
@@ -344,6 +508,7 @@ impl Application {
                     event: WindowEvent::Resized(size), ..
                 } => {
                     if size.width > 1 && size.height > 1 {
+                        self.window.inputs.size_scale = [size.width as f32 / 1600.0, size.height as f32 / 900.0];
                         if let Some(gpu) = &mut self.window.gpu {
                             info!("Window resized, telling gpu data");
                             gpu.resize(size.width, size.height);
@@ -355,31 +520,6 @@ impl Application {
                         }
                     }
                 }
-                Event::WindowEvent {
-                    event: WindowEvent::KeyboardInput {
-                        input,
-                        is_synthetic,
-                        ..
-                    }, ..
-                } => {
-                    if !is_synthetic {
-                        if let Some(key) = input.virtual_keycode {
-                            match input.state {
-                                ElementState::Pressed => {
-                                    pressed_keys.insert(key);
-                                }
-                                ElementState::Released => {
-                                    released_keys.insert(key);
-                                }
-                            }
-                        }
-                    }
-                }
-                Event::WindowEvent {
-                    event: WindowEvent::Touch(touch), ..
-                } => {
-                    self.window.inputs.points.insert(touch.id, Pointer::from(touch));
-                }
                 Event::RedrawRequested(_) => {
                     if !game_draw_requested {
                         log::trace!("System Redraw Requested");
@@ -387,21 +527,30 @@ impl Application {
                     self.render_once();
                     game_draw_requested = false;
                 }
+                Event::LoopDestroyed => {
+                    if let Some(recorder) = &self.recorder {
+                        recorder.finish();
+                    }
+                    self.window.settings.flush();
+                }
                 Event::MainEventsCleared => {
-                    if !pressed_keys.is_empty() || !released_keys.is_empty() {
-                        log::trace!(target: "InputTrace", "process pressed_key {:?} and released {:?}", pressed_keys, released_keys);
-                        self.window.inputs.process(&pressed_keys, &released_keys);
-                        pressed_keys.clear();
-                        released_keys.clear();
+                    // one recorded frame a loop like the headless replay
+                    if let Some(frame) = self.replay.as_mut().and_then(|x| x.next_frame()) {
+                        let pixels_per_point = self.window.egui_ctx.pixels_per_point();
+                        for event in frame.events {
+                            self.handle_input_at(event.time, event.kind);
+                            self.replay_egui.push(event.kind, pixels_per_point);
+                        }
                     }
+                    self.bake_inputs();
                     if self.running {
                         let LoopState {
                             control_flow: c_f,
                             render
                         } = self.loop_once();
-                        if render {
+                        if let (true, Some(window)) = (render, &self.window.window) {
                             game_draw_requested = true;
-                            self.window.window.request_redraw();
+                            window.request_redraw();
                         }
                         *control_flow = c_f;
                     } else {
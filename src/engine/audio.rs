@@ -1,5 +1,14 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+use kira::dsp::Frame;
 use kira::manager::{AudioManager, AudioManagerSettings};
 use kira::manager::backend::cpal::CpalBackend;
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+
+const TICK_SAMPLE_RATE: u32 = 44100;
+/// The seconds of a metronome tick
+const TICK_LENGTH: f32 = 0.04;
 
 pub struct AudioData {
     pub manager: AudioManager<CpalBackend>,
@@ -15,4 +24,20 @@ impl AudioData {
 }
 
 
-impl AudioData {}
\ No newline at end of file
+impl AudioData {}
+
+/// Make a short decaying sine beep for the metronome, the accent one is higher.
+pub fn metronome_tick(accent: bool) -> StaticSoundData {
+    let freq = if accent { 1760.0 } else { 1320.0 };
+    let len = (TICK_SAMPLE_RATE as f32 * TICK_LENGTH) as usize;
+    let frames = (0..len).map(|i| {
+        let t = i as f32 / TICK_SAMPLE_RATE as f32;
+        let envelope = 1.0 - t / TICK_LENGTH;
+        Frame::from_mono((t * freq * TAU).sin() * envelope * envelope * 0.5)
+    }).collect();
+    StaticSoundData {
+        sample_rate: TICK_SAMPLE_RATE,
+        frames: Arc::new(frames),
+        settings: StaticSoundSettings::default(),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::SessionHistory;
+
+/// Never click faster than this cps whatever the profile says
+const MAX_BOT_CPS: f64 = 100.0;
+
+/// How the bot decides the cps over the time since it starts
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotProfile {
+    Constant { cps: f64 },
+    /// Goes from `from` to `to` linearly in `secs` and keeps `to` after
+    Ramping { from: f64, to: f64, secs: f64 },
+    /// Every interval is off by a normal distributed fraction with the stddev `jitter`
+    Jittered { cps: f64, jitter: f64, seed: u64 },
+    /// Clicks with the recorded intervals in seconds, loops when they run out
+    Replay { intervals: Vec<f64> },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BotDifficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+    /// Replays the best recorded session
+    Replay,
+}
+
+impl BotDifficulty {
+    pub const ALL: [BotDifficulty; 5] = [BotDifficulty::Easy, BotDifficulty::Normal, BotDifficulty::Hard, BotDifficulty::Insane, BotDifficulty::Replay];
+
+    /// The replay falls back to normal if there is no session to replay
+    pub fn profile(&self, history: &SessionHistory) -> BotProfile {
+        match self {
+            BotDifficulty::Easy => BotProfile::Constant { cps: 4.0 },
+            BotDifficulty::Normal => BotProfile::Jittered { cps: 7.0, jitter: 0.15, seed: 7 },
+            BotDifficulty::Hard => BotProfile::Ramping { from: 6.0, to: 11.0, secs: 10.0 },
+            BotDifficulty::Insane => BotProfile::Jittered { cps: 14.0, jitter: 0.08, seed: 14 },
+            BotDifficulty::Replay => history.sessions().iter()
+                .filter(|x| !x.intervals.is_empty() && x.verdict.as_ref().map(|v| v.is_clean()).unwrap_or(true))
+                .max_by(|a, b| a.max_cps.total_cmp(&b.max_cps))
+                .map(|x| BotProfile::Replay { intervals: x.intervals.clone() })
+                .unwrap_or_else(|| BotDifficulty::Normal.profile(history)),
+        }
+    }
+}
+
+/// Generates the click times from the profile, the same profile and start give the same clicks.
+pub struct Bot {
+    profile: BotProfile,
+    start: Duration,
+    next: Duration,
+    /// Clicks made
+    count: usize,
+    rng: StdRng,
+}
+
+impl Bot {
+    pub fn new(profile: BotProfile, start: Duration) -> Self {
+        let seed = match &profile {
+            BotProfile::Jittered { seed, .. } => *seed,
+            _ => 0,
+        };
+        Self {
+            profile,
+            start,
+            next: start,
+            count: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Take the click times until `now`
+    pub fn clicks_until(&mut self, now: Duration) -> Vec<Duration> {
+        let mut ret = vec![];
+        while self.next <= now {
+            ret.push(self.next);
+            let interval = self.interval().max(1.0 / MAX_BOT_CPS);
+            self.count += 1;
+            if !interval.is_finite() {
+                // stopped clicking
+                self.next = Duration::MAX;
+                break;
+            }
+            self.next += Duration::from_secs_f64(interval);
+        }
+        ret
+    }
+
+    /// The seconds between the last click and the next one
+    fn interval(&mut self) -> f64 {
+        let sec = self.next.saturating_sub(self.start).as_secs_f64();
+        let by_cps = |cps: f64| if cps > 0.0 { 1.0 / cps } else { f64::INFINITY };
+        match &self.profile {
+            BotProfile::Constant { cps } => by_cps(*cps),
+            BotProfile::Ramping { from, to, secs } => {
+                let t = if *secs > 0.0 { (sec / secs).min(1.0) } else { 1.0 };
+                by_cps(from + (to - from) * t)
+            }
+            BotProfile::Jittered { cps, jitter, .. } => {
+                // Box-Muller
+                let (u1, u2): (f64, f64) = (1.0 - self.rng.gen::<f64>(), self.rng.gen());
+                let normal = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+                by_cps(*cps) * (1.0 + normal * jitter).max(0.0)
+            }
+            BotProfile::Replay { intervals } => {
+                if intervals.is_empty() {
+                    f64::INFINITY
+                } else {
+                    intervals[self.count % intervals.len()]
+                }
+            }
+        }
+    }
+}
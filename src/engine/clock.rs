@@ -0,0 +1,58 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Monotonic time source for the game states.
+///
+/// The time is the duration since the clock created and never goes backwards.
+pub trait Clock: 'static {
+    fn now(&self) -> Duration;
+}
+
+/// The real clock backed by [`Instant`]
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        Instant::now().saturating_duration_since(self.start)
+    }
+}
+
+/// The clock only moves when told to.
+///
+/// Clones share the same time so one can be given to the application and the other kept to drive it.
+#[derive(Default, Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl ManualClock {
+    pub fn advance(&self, dur: Duration) {
+        self.now.set(self.now.get() + dur);
+    }
+
+    pub fn advance_secs(&self, secs: f32) {
+        self.advance(Duration::from_secs_f32(secs));
+    }
+
+    /// Set the time, ignored if it is earlier than now.
+    pub fn set(&self, now: Duration) {
+        self.now.set(self.now.get().max(now));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
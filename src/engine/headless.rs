@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use egui::{Event, Pos2, RawInput, Rect, Vec2};
+use winit::event::{MouseButton, TouchPhase, VirtualKeyCode};
+
+use crate::engine::app::{Application, WindowInstance};
+use crate::engine::{Clock, EguiEvents, GameState, InputEventKind, InputRecorder, InputRecording, LoopState, ManualClock, StateData};
+
+/// Drive the game states without window, gpu and audio.
+///
+/// Keys and mouse buttons are baked at the next [`HeadlessApplication::step`],
+/// the same as the real loop does at `MainEventsCleared`.
+/// The window clock is a [`ManualClock`] advanced by the `dt` of every step.
+pub struct HeadlessApplication {
+    app: Application,
+    egui: EguiEvents,
+    screen_size: Vec2,
+    time: f64,
+    clock: ManualClock,
+}
+
+impl HeadlessApplication {
+    pub fn new(mut start: impl GameState) -> Self {
+        let mut app = Application::headless();
+        let clock = ManualClock::default();
+        app.window.clock = Box::new(clock.clone());
+        start.start(&mut StateData {
+            window: &mut app.window,
+            dt: 0.0,
+        });
+        app.states.push(Box::new(start));
+        Self {
+            app,
+            egui: EguiEvents::default(),
+            screen_size: Vec2::new(1600.0, 900.0),
+            time: 0.0,
+            clock,
+        }
+    }
+
+    pub fn with_screen_size(mut self, width: f32, height: f32) -> Self {
+        self.screen_size = Vec2::new(width, height);
+        self.app.window.inputs.size_scale = [width / 1600.0, height / 900.0];
+        self
+    }
+
+    /// Feed the input event at the current clock time, egui gets the same event too.
+    pub fn input(&mut self, kind: InputEventKind) {
+        self.app.handle_input(kind);
+        self.egui.push(kind, 1.0);
+    }
+
+    pub fn press_key(&mut self, key: VirtualKeyCode) {
+        self.input(InputEventKind::Key { key, pressed: true, repeat: false, synthetic: false });
+    }
+
+    pub fn release_key(&mut self, key: VirtualKeyCode) {
+        self.input(InputEventKind::Key { key, pressed: false, repeat: false, synthetic: false });
+    }
+
+    /// Move the cursor to the position in screen pixels
+    pub fn move_cursor(&mut self, x: f32, y: f32) {
+        self.input(InputEventKind::CursorMoved { x: x as f64, y: y as f64 });
+    }
+
+    pub fn press_mouse(&mut self, button: MouseButton) {
+        self.input(InputEventKind::Mouse { button, pressed: true });
+    }
+
+    pub fn release_mouse(&mut self, button: MouseButton) {
+        self.input(InputEventKind::Mouse { button, pressed: false });
+    }
+
+    pub fn scroll(&mut self, x: f32, y: f32) {
+        self.input(InputEventKind::Wheel { x, y });
+    }
+
+    /// Touch at the position in screen pixels
+    pub fn touch(&mut self, id: u64, phase: TouchPhase, x: f32, y: f32) {
+        self.input(InputEventKind::Touch { id, phase, x: x as f64, y: y as f64 });
+    }
+
+    /// Record the frames from now on
+    pub fn start_recording(&mut self) {
+        self.app.recorder = Some(InputRecorder::new(self.app.window.inputs.size_scale));
+    }
+
+    pub fn take_recording(&mut self) -> Option<InputRecording> {
+        self.app.recorder.take().map(|r| r.recording)
+    }
+
+    /// Run all frames of the recording with the recorded times and events.
+    pub fn replay(&mut self, recording: &InputRecording) {
+        self.app.window.inputs.size_scale = recording.size_scale;
+        for frame in &recording.frames {
+            for event in &frame.events {
+                self.clock.set(event.time);
+                self.input(event.kind);
+            }
+            self.step_to(frame.time);
+        }
+    }
+
+    /// Queue a raw egui event for the next ui pass.
+    pub fn push_egui_event(&mut self, event: Event) {
+        self.egui.events.push(event);
+    }
+
+    /// Advance the clock by `dt` and run one frame.
+    pub fn step(&mut self, dt: f32) -> LoopState {
+        let time = self.clock.now() + Duration::from_secs_f32(dt);
+        self.step_to(time)
+    }
+
+    /// Set the clock to the time, bake the inputs, run the logic once and then the ui pass if any state wants to render.
+    pub fn step_to(&mut self, time: Duration) -> LoopState {
+        let dt = time.saturating_sub(self.clock.now()).as_secs_f32();
+        self.clock.set(time);
+        self.app.bake_inputs();
+        if !self.app.running {
+            return LoopState::WAIT_ALL;
+        }
+        let loop_state = self.app.loop_once();
+        if loop_state.render && self.app.running {
+            self.render(dt);
+        }
+        loop_state
+    }
+
+    /// Run the ui pass with the queued egui events.
+    pub fn render(&mut self, dt: f32) {
+        let raw_input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, self.screen_size)),
+            pixels_per_point: Some(1.0),
+            time: Some(self.time),
+            predicted_dt: dt,
+            events: self.egui.take(),
+            has_focus: true,
+            ..Default::default()
+        };
+        self.time += dt as f64;
+        let _ = self.app.run_ui(raw_input, dt);
+        self.app.post_ui_render(dt);
+    }
+
+    /// The window instance shared by the states, to inspect the settings, history and inputs
+    pub fn window(&self) -> &WindowInstance {
+        &self.app.window
+    }
+
+    pub fn window_mut(&mut self) -> &mut WindowInstance {
+        &mut self.app.window
+    }
+
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.app.running
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.app.states.len()
+    }
+
+    /// Get the top state if it is `T`
+    pub fn top<T: GameState>(&self) -> Option<&T> {
+        self.app.states.last().and_then(|s| (**s).as_any().downcast_ref())
+    }
+
+    pub fn top_mut<T: GameState>(&mut self) -> Option<&mut T> {
+        self.app.states.last_mut().and_then(|s| (**s).as_any_mut().downcast_mut())
+    }
+}
@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{analyze_clicks, config_dir, unix_now, Verdict};
+
+/// Bump it when the history format changes
+pub const HISTORY_VERSION: u32 = 1;
+
+/// The mode name of the free click test
+pub const FREE_MODE: &str = "Free";
+
+/// How the sessions of a mode are ranked
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RankKey {
+    /// The highest max cps
+    MaxCps,
+    /// The shortest duration, the count modes race to the clicks
+    MinDuration,
+    /// The longest duration, the endurance modes keep the pace as long as they can
+    MaxDuration,
+}
+
+impl RankKey {
+    /// The key of the mode by the name the click test gives it
+    pub fn of_mode(mode: &str) -> Self {
+        if mode.starts_with("Count ") {
+            RankKey::MinDuration
+        } else if mode.starts_with("Endurance ") {
+            RankKey::MaxDuration
+        } else {
+            RankKey::MaxCps
+        }
+    }
+
+    /// The better session is less
+    pub fn order(&self, a: &SessionRecord, b: &SessionRecord) -> Ordering {
+        match self {
+            RankKey::MaxCps => b.max_cps.total_cmp(&a.max_cps),
+            RankKey::MinDuration => a.duration.cmp(&b.duration),
+            RankKey::MaxDuration => b.duration.cmp(&a.duration),
+        }
+    }
+}
+
+/// One finished click session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// Unix seconds when the session finished
+    pub timestamp: u64,
+    /// Sessions are ranked against the ones with the same mode
+    pub mode: String,
+    pub duration: Duration,
+    pub clicks: usize,
+    pub max_cps: f64,
+    /// Seconds between the clicks
+    pub intervals: Vec<f64>,
+    /// `None` for the sessions saved before the detection
+    #[serde(default)]
+    pub verdict: Option<Verdict>,
+}
+
+impl SessionRecord {
+    /// Build the record from the click times, `None` if there are not enough clicks to rate.
+    ///
+    /// `synthetic` is how many synthetic key presses the input dropped during the session.
+    pub fn from_clicks(mode: impl Into<String>, clicks: &[Duration], max_cps: f64, synthetic: usize) -> Option<Self> {
+        if clicks.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            timestamp: unix_now(),
+            mode: mode.into(),
+            duration: clicks[clicks.len() - 1] - clicks[0],
+            clicks: clicks.len(),
+            max_cps,
+            intervals: clicks.windows(2).map(|x| (x[1] - x[0]).as_secs_f64()).collect(),
+            verdict: Some(analyze_clicks(clicks, synthetic)),
+        })
+    }
+
+    /// The clicks per second over the whole session
+    pub fn avg_cps(&self) -> f64 {
+        let sec = self.duration.as_secs_f64();
+        if sec > 0.0 { self.clicks as f64 / sec } else { 0.0 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HistoryData {
+    version: u32,
+    sessions: Vec<SessionRecord>,
+}
+
+/// The finished sessions saved in the local json file.
+pub struct SessionHistory {
+    data: HistoryData,
+    path: Option<PathBuf>,
+}
+
+impl SessionHistory {
+    /// The history never saved
+    pub fn in_memory() -> Self {
+        Self {
+            data: HistoryData { version: HISTORY_VERSION, sessions: vec![] },
+            path: None,
+        }
+    }
+
+    /// Load from the file, start empty if it does not exist.
+    ///
+    /// The unreadable file is moved to `.bak` before starting a new one,
+    /// the history is not saved if it cannot be moved or read.
+    pub fn load(path: PathBuf) -> Self {
+        let data = match std::fs::read(&path) {
+            Ok(data) => match Self::parse(&data) {
+                Ok(data) => data,
+                Err(e) => {
+                    let mut backup = path.clone().into_os_string();
+                    backup.push(".bak");
+                    log::warn!("Load history {:?} failed for {:?}, moving it to {:?}", path, e, backup);
+                    if let Err(e) = std::fs::rename(&path, &backup) {
+                        log::warn!("Move history to {:?} failed for {:?}, it will not be saved", backup, e);
+                        return Self::in_memory();
+                    }
+                    Self::in_memory().data
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::in_memory().data,
+            Err(e) => {
+                log::warn!("Read history {:?} failed for {:?}, it will not be saved", path, e);
+                return Self::in_memory();
+            }
+        };
+        Self {
+            data,
+            path: Some(path),
+        }
+    }
+
+    /// Load from the per-user config directory
+    pub fn load_default() -> Self {
+        match config_dir() {
+            Some(dir) => Self::load(dir.join("history.json")),
+            None => Self::in_memory(),
+        }
+    }
+
+    fn parse(data: &[u8]) -> anyhow::Result<HistoryData> {
+        let data: HistoryData = serde_json::from_slice(data)?;
+        if data.version != HISTORY_VERSION {
+            return Err(anyhow!("Unsupported history version {}, expected {}", data.version, HISTORY_VERSION));
+        }
+        Ok(data)
+    }
+
+    /// All sessions from the oldest
+    pub fn sessions(&self) -> &[SessionRecord] {
+        &self.data.sessions
+    }
+
+    /// All modes having sessions, sorted
+    pub fn modes(&self) -> BTreeSet<&str> {
+        self.data.sessions.iter().map(|x| x.mode.as_str()).collect()
+    }
+
+    /// The sessions of the mode from the oldest
+    pub fn by_mode<'a>(&'a self, mode: &'a str) -> impl Iterator<Item=&'a SessionRecord> + 'a {
+        self.data.sessions.iter().filter(move |x| x.mode == mode)
+    }
+
+    /// The best sessions of the mode by its [`RankKey`]
+    pub fn leaderboard(&self, mode: &str, count: usize) -> Vec<&SessionRecord> {
+        let key = RankKey::of_mode(mode);
+        let mut ret: Vec<_> = self.data.sessions.iter().filter(|x| x.mode == mode).collect();
+        ret.sort_by(|a, b| key.order(a, b));
+        ret.truncate(count);
+        ret
+    }
+
+    /// Add the session and save the history
+    pub fn push(&mut self, record: SessionRecord) {
+        self.data.sessions.push(record);
+        self.save();
+    }
+
+    pub fn clear(&mut self) {
+        self.data.sessions.clear();
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            let result = serde_json::to_vec(&self.data).map_err(anyhow::Error::from).and_then(|data| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Ok(std::fs::write(path, data)?)
+            });
+            if let Err(e) = result {
+                log::warn!("Save history to {:?} failed for {:?}", path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The session of the clicks every `interval` seconds
+    fn session(mode: &str, clicks: u64, interval: u64, max_cps: f64) -> SessionRecord {
+        let clicks: Vec<_> = (0..clicks).map(|i| Duration::from_millis(i * interval)).collect();
+        SessionRecord::from_clicks(mode, &clicks, max_cps, 0).unwrap()
+    }
+
+    /// The durations of the leaderboard from the best
+    fn ranked(history: &SessionHistory, mode: &str) -> Vec<u128> {
+        history.leaderboard(mode, 10).iter().map(|x| x.duration.as_millis()).collect()
+    }
+
+    #[test]
+    fn ranks_by_the_mode() {
+        let mut history = SessionHistory::in_memory();
+        for (interval, max_cps) in [(100, 11.0), (80, 9.0), (120, 7.0)] {
+            for mode in ["Timed 10s", "Count 50", "Endurance 5 CPS"] {
+                history.push(session(mode, 50, interval, max_cps));
+            }
+        }
+        assert_eq!(ranked(&history, "Timed 10s"), [4900, 3920, 5880]);
+        assert_eq!(ranked(&history, "Count 50"), [3920, 4900, 5880]);
+        assert_eq!(ranked(&history, "Endurance 5 CPS"), [5880, 4900, 3920]);
+        assert_eq!(history.leaderboard("Count 50", 1).len(), 1);
+    }
+}
@@ -1,31 +1,374 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::mem::swap;
+use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalPosition;
-use winit::event::{Touch, TouchPhase, VirtualKeyCode};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode, WindowEvent};
+
+/// Keep this many positions of the pointer for the velocity
+const POINTER_HISTORY: usize = 16;
+/// Only use the history in this duration for the velocity
+const VELOCITY_WINDOW: Duration = Duration::from_millis(100);
+/// The smaller side of a dragged region in the design coordinates, a tap is not a region
+pub const MIN_REGION_SIZE: f32 = 50.0;
 
-#[allow(unused)]
 #[derive(Debug, Clone)]
 pub struct Pointer {
     id: u64,
     loc: PhysicalPosition<f64>,
     phase: TouchPhase,
+    /// (time, location) from old to new
+    history: VecDeque<(Duration, PhysicalPosition<f64>)>,
+}
+
+impl Pointer {
+    fn new(id: u64, loc: PhysicalPosition<f64>, now: Duration) -> Self {
+        Self {
+            id,
+            loc,
+            phase: TouchPhase::Started,
+            history: VecDeque::from([(now, loc)]),
+        }
+    }
+
+    fn update(&mut self, phase: TouchPhase, loc: PhysicalPosition<f64>, now: Duration) {
+        self.loc = loc;
+        self.phase = phase;
+        if self.history.len() >= POINTER_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((now, loc));
+    }
+
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The location in physical pixels
+    #[inline]
+    pub fn location(&self) -> PhysicalPosition<f64> {
+        self.loc
+    }
+
+    /// The location in the 1600x900 design coordinates
+    #[inline]
+    pub fn design_position(&self, size_scale: [f32; 2]) -> [f32; 2] {
+        [self.loc.x as f32 / size_scale[0], self.loc.y as f32 / size_scale[1]]
+    }
+
+    #[inline]
+    pub fn phase(&self) -> TouchPhase {
+        self.phase
+    }
+
+    /// The velocity in physical pixels per second over the recent history
+    pub fn velocity(&self) -> [f32; 2] {
+        let (last_time, last_loc) = match self.history.back() {
+            Some(x) => *x,
+            None => return [0.0, 0.0],
+        };
+        let (first_time, first_loc) = self.history.iter()
+            .find(|(t, _)| last_time.saturating_sub(*t) <= VELOCITY_WINDOW)
+            .copied()
+            .unwrap_or((last_time, last_loc));
+        let sec = last_time.saturating_sub(first_time).as_secs_f32();
+        if sec <= 0.0 {
+            return [0.0, 0.0];
+        }
+        [(last_loc.x - first_loc.x) as f32 / sec, (last_loc.y - first_loc.y) as f32 / sec]
+    }
+}
+
+/// The named things the player can do, states should query these instead of raw keys.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    LeftPlayerHit,
+    RightPlayerHit,
+    Back,
+    Confirm,
+    StartClickTest,
+}
+
+impl Action {
+    pub const ALL: [Action; 5] = [Action::LeftPlayerHit, Action::RightPlayerHit, Action::Back, Action::Confirm, Action::StartClickTest];
+}
+
+/// The rect in the 1600x900 design coordinates
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TouchRegion {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl TouchRegion {
+    pub const LEFT_HALF: TouchRegion = TouchRegion { min: [0.0, 0.0], max: [800.0, 900.0] };
+    pub const RIGHT_HALF: TouchRegion = TouchRegion { min: [800.0, 0.0], max: [1600.0, 900.0] };
+
+    /// The region between the two corners in any order
+    pub fn from_corners(a: [f32; 2], b: [f32; 2]) -> Self {
+        Self {
+            min: [a[0].min(b[0]), a[1].min(b[1])],
+            max: [a[0].max(b[0]), a[1].max(b[1])],
+        }
+    }
+
+    pub fn contains(&self, pos: [f32; 2]) -> bool {
+        self.min[0] <= pos[0] && pos[0] < self.max[0] && self.min[1] <= pos[1] && pos[1] < self.max[1]
+    }
+}
+
+/// Capture a touch region for rebinding by dragging a touch over it.
+#[derive(Debug, Default)]
+pub struct RegionCapture {
+    /// The dragging touch and where it started in the design coordinates
+    start: Option<(u64, [f32; 2])>,
+}
+
+impl RegionCapture {
+    /// Follow the touches of this frame, get the region when the drag ends
+    pub fn update(&mut self, inputs: &BakedInputs) -> Option<TouchRegion> {
+        for event in inputs.frame_events() {
+            if let InputEventKind::Touch { id, phase, x, y } = event.kind {
+                let pos = [x as f32 / inputs.size_scale[0], y as f32 / inputs.size_scale[1]];
+                match (phase, self.start) {
+                    (TouchPhase::Started, None) => self.start = Some((id, pos)),
+                    (TouchPhase::Ended, Some((start_id, start))) if start_id == id => {
+                        self.start = None;
+                        let region = TouchRegion::from_corners(start, pos);
+                        if region.max[0] - region.min[0] >= MIN_REGION_SIZE && region.max[1] - region.min[1] >= MIN_REGION_SIZE {
+                            return Some(region);
+                        }
+                    }
+                    (TouchPhase::Cancelled, Some((start_id, _))) if start_id == id => self.start = None,
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+
+    pub fn clear(&mut self) {
+        self.start = None;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Touch(TouchRegion),
+}
+
+/// The binding table for all actions, one action can have many bindings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionBindings {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for ActionBindings {
+    fn default() -> Self {
+        let mut bindings = HashMap::new();
+        bindings.insert(Action::LeftPlayerHit, vec![Binding::Key(VirtualKeyCode::A),
+                                                    Binding::Touch(TouchRegion::LEFT_HALF)]);
+        bindings.insert(Action::RightPlayerHit, vec![Binding::Key(VirtualKeyCode::Key6),
+                                                     Binding::Key(VirtualKeyCode::Numpad6),
+                                                     Binding::Touch(TouchRegion::RIGHT_HALF)]);
+        bindings.insert(Action::Back, vec![Binding::Key(VirtualKeyCode::Escape)]);
+        bindings.insert(Action::Confirm, vec![Binding::Key(VirtualKeyCode::Return)]);
+        bindings.insert(Action::StartClickTest, vec![Binding::Key(VirtualKeyCode::S)]);
+        Self { bindings }
+    }
+}
+
+impl ActionBindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map(|x| &x[..]).unwrap_or(&[])
+    }
+
+    /// Add the binding to the action if it is not bound yet.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: &Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|x| x != binding);
+        }
+    }
+
+    pub fn set(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputEventKind {
+    /// `repeat` if the key is already held, `synthetic` if the platform made it up
+    Key { key: VirtualKeyCode, pressed: bool, repeat: bool, synthetic: bool },
+    Mouse { button: MouseButton, pressed: bool },
+    /// The location is in physical pixels
+    Touch { id: u64, phase: TouchPhase, x: f64, y: f64 },
+    CursorMoved { x: f64, y: f64 },
+    CursorLeft,
+    /// The delta is in lines
+    Wheel { x: f32, y: f32 },
+}
+
+/// The input event with the window clock time it happened at
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub time: Duration,
+    pub kind: InputEventKind,
+}
+
+impl InputEventKind {
+    /// Translate the window event we care about
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::KeyboardInput { input, is_synthetic, .. } => InputEventKind::Key {
+                key: input.virtual_keycode?,
+                pressed: input.state == ElementState::Pressed,
+                repeat: false,
+                synthetic: *is_synthetic,
+            },
+            WindowEvent::MouseInput { state, button, .. } => InputEventKind::Mouse {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            },
+            WindowEvent::Touch(touch) => InputEventKind::Touch {
+                id: touch.id,
+                phase: touch.phase,
+                x: touch.location.x,
+                y: touch.location.y,
+            },
+            WindowEvent::CursorMoved { position, .. } => InputEventKind::CursorMoved { x: position.x, y: position.y },
+            WindowEvent::CursorLeft { .. } => InputEventKind::CursorLeft,
+            WindowEvent::MouseWheel { delta, .. } => match *delta {
+                MouseScrollDelta::LineDelta(x, y) => InputEventKind::Wheel { x, y },
+                // the same points per line as egui
+                MouseScrollDelta::PixelDelta(pos) => InputEventKind::Wheel { x: pos.x as f32 / 50.0, y: pos.y as f32 / 50.0 },
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl Binding {
+    /// Does the event press this binding, key repeats and the synthetic keys are not presses.
+    pub fn is_pressed_by(&self, event: &InputEvent, size_scale: [f32; 2]) -> bool {
+        match (self, &event.kind) {
+            (Binding::Key(k), InputEventKind::Key { key, pressed: true, repeat: false, synthetic: false }) => k == key,
+            (Binding::Mouse(b), InputEventKind::Mouse { button, pressed: true }) => b == button,
+            (Binding::Touch(region), InputEventKind::Touch { phase: TouchPhase::Started, x, y, .. }) => {
+                region.contains([*x as f32 / size_scale[0], *y as f32 / size_scale[1]])
+            }
+            _ => false
+        }
+    }
+
+    /// Is the event a synthetic press of this key, dropped by [`Self::is_pressed_by`] but counted for the verdict.
+    pub fn is_synthetic_press_by(&self, event: &InputEvent) -> bool {
+        match (self, &event.kind) {
+            (Binding::Key(k), InputEventKind::Key { key, pressed: true, repeat: false, synthetic: true }) => k == key,
+            _ => false
+        }
+    }
+}
+
+/// The physical source of a press, each touch is its own source.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Touch(u64),
+}
+
+impl InputSource {
+    /// Get the source and whether it is pressed by the event
+    pub fn from_event(kind: &InputEventKind) -> Option<(Self, bool)> {
+        Some(match *kind {
+            InputEventKind::Key { key, pressed, .. } => (InputSource::Key(key), pressed),
+            InputEventKind::Mouse { button, pressed } => (InputSource::Mouse(button), pressed),
+            InputEventKind::Touch { id, phase: TouchPhase::Started, .. } => (InputSource::Touch(id), true),
+            InputEventKind::Touch { id, phase: TouchPhase::Ended | TouchPhase::Cancelled, .. } => (InputSource::Touch(id), false),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct SourceState {
+    pressed: bool,
+    last_change: Duration,
 }
 
-impl From<Touch> for Pointer {
-    fn from(touch: Touch) -> Self {
+/// Track the press and release of every source.
+///
+/// A press counts only if the source was released, and the switch settled for the debounce time
+/// since its last change.
+#[derive(Debug, Clone, Default)]
+pub struct Debouncer {
+    pub debounce: Duration,
+    sources: HashMap<InputSource, SourceState>,
+}
+
+impl Debouncer {
+    pub fn new(debounce: Duration) -> Self {
         Self {
-            id: touch.id,
-            loc: touch.location,
-            phase: touch.phase,
+            debounce,
+            sources: Default::default(),
         }
     }
+
+    /// Feed the event and get whether it is a press to count
+    pub fn accept(&mut self, event: &InputEvent) -> bool {
+        let (source, pressed) = if let Some(x) = InputSource::from_event(&event.kind) { x } else { return false; };
+        match self.sources.get_mut(&source) {
+            Some(state) => {
+                if state.pressed == pressed {
+                    return false;
+                }
+                let settled = event.time.saturating_sub(state.last_change) >= self.debounce;
+                // the bounces do not move the settle time
+                if settled {
+                    state.last_change = event.time;
+                }
+                state.pressed = pressed;
+                pressed && settled
+            }
+            None => {
+                self.sources.insert(source, SourceState { pressed, last_change: event.time });
+                pressed
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sources.clear();
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct RawInputData {
-    pub points: HashMap<usize, Pointer>,
+    /// The active pointers, the ones ended in this frame are kept until the next frame
+    pub points: HashMap<u64, Pointer>,
+    /// The pointers began in this frame
+    pub touch_began: Vec<Pointer>,
+    /// The pointers moved in this frame
+    pub touch_moved: Vec<Pointer>,
+    /// The pointers ended or cancelled in this frame
+    pub touch_ended: Vec<Pointer>,
     pub pressing: HashSet<VirtualKeyCode>,
+    pub mouse_pressing: HashSet<MouseButton>,
+    /// The cursor position in physical pixels
+    pub cursor: Option<PhysicalPosition<f64>>,
+    /// The wheel delta in lines in this frame
+    pub wheel: [f32; 2],
 }
 
 #[derive(Default)]
@@ -37,12 +380,27 @@ pub struct BakedInputs {
     pub cur_temp_game_input: RawInputData,
     /// only swap in states.game tick
     pub last_temp_game_input: RawInputData,
-    pub points: HashMap<u64, Pointer>,
     pub pressed_any_cur_frame: usize,
+    pub bindings: ActionBindings,
+    /// physical size / design size, same as [`crate::engine::WgpuData::size_scale`]
+    pub size_scale: [f32; 2],
+    /// The events not swapped to the frame yet, in time order
+    event_queue: Vec<InputEvent>,
+    /// The events came in before this frame
+    frame_events: Vec<InputEvent>,
+    /// The keys held for the event queue, to mark the repeats
+    event_held_keys: HashSet<VirtualKeyCode>,
 }
 
 
 impl BakedInputs {
+    pub fn new(size_scale: [f32; 2]) -> Self {
+        Self {
+            size_scale,
+            ..Default::default()
+        }
+    }
+
     pub fn process(&mut self, pressed: &HashSet<VirtualKeyCode>, released: &HashSet<VirtualKeyCode>) {
         for key in pressed.iter() {
             self.cur_temp_input.pressing.insert(*key);
@@ -58,6 +416,96 @@ impl BakedInputs {
             }
         }
     }
+
+    pub fn process_mouse(&mut self, pressed: &HashSet<MouseButton>, released: &HashSet<MouseButton>) {
+        for button in pressed.iter() {
+            self.cur_temp_input.mouse_pressing.insert(*button);
+            self.cur_temp_game_input.mouse_pressing.insert(*button);
+        }
+
+        for button in released.iter() {
+            if self.last_temp_game_input.mouse_pressing.contains(button) {
+                self.cur_temp_game_input.mouse_pressing.remove(button);
+            }
+            if self.cur_frame_input.mouse_pressing.contains(button) {
+                self.cur_temp_input.mouse_pressing.remove(button);
+            }
+        }
+    }
+
+    pub fn set_cursor(&mut self, pos: Option<PhysicalPosition<f64>>) {
+        self.cur_temp_input.cursor = pos;
+        self.cur_temp_game_input.cursor = pos;
+    }
+
+    pub fn process_touch(&mut self, id: u64, phase: TouchPhase, loc: PhysicalPosition<f64>, now: Duration) {
+        let input = &mut self.cur_temp_input;
+        match phase {
+            TouchPhase::Started => {
+                let pointer = Pointer::new(id, loc, now);
+                input.touch_began.push(pointer.clone());
+                input.points.insert(id, pointer);
+            }
+            TouchPhase::Moved => {
+                if let Some(pointer) = input.points.get_mut(&id) {
+                    pointer.update(phase, loc, now);
+                    input.touch_moved.push(pointer.clone());
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                if let Some(pointer) = input.points.get_mut(&id) {
+                    pointer.update(phase, loc, now);
+                    input.touch_ended.push(pointer.clone());
+                    // keep it for one frame if the frame has not seen it
+                    if self.cur_frame_input.points.contains_key(&id) {
+                        input.points.remove(&id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add the wheel delta in lines
+    pub fn add_wheel(&mut self, x: f32, y: f32) {
+        for input in [&mut self.cur_temp_input, &mut self.cur_temp_game_input] {
+            input.wheel[0] += x;
+            input.wheel[1] += y;
+        }
+    }
+
+    /// Record the event into the queue, it will be in the frame events after the next frame swap.
+    pub fn push_event(&mut self, time: Duration, mut kind: InputEventKind) {
+        if let InputEventKind::Key { key, pressed, repeat, .. } = &mut kind {
+            *repeat = if *pressed {
+                !self.event_held_keys.insert(*key)
+            } else {
+                self.event_held_keys.remove(key);
+                false
+            };
+        }
+        self.event_queue.push(InputEvent { time, kind });
+    }
+
+    /// The events came in before this frame, in time order
+    pub fn frame_events(&self) -> &[InputEvent] {
+        &self.frame_events
+    }
+
+    /// Take the events of this frame so the states after will not see them
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, InputEvent> {
+        self.frame_events.drain(..)
+    }
+
+    /// Does the event press any binding of the action
+    pub fn action_pressed_by(&self, action: Action, event: &InputEvent) -> bool {
+        self.bindings.get(action).iter().any(|b| b.is_pressed_by(event, self.size_scale))
+    }
+
+    /// Is the event a synthetic key press of one of the action bindings
+    pub fn action_synthetic_by(&self, action: Action, event: &InputEvent) -> bool {
+        self.bindings.get(action).iter().any(|b| b.is_synthetic_press_by(event))
+    }
+
     /// save current input to last
     /// make current temp input to current frame input
     pub(in crate::engine) fn swap_frame(&mut self) {
@@ -65,6 +513,13 @@ impl BakedInputs {
         swap(&mut self.cur_frame_input, &mut self.last_frame_input);
         //clone for not lose temp info
         self.cur_frame_input = self.cur_temp_input.clone();
+        self.cur_temp_input.wheel = [0.0, 0.0];
+        self.cur_temp_input.touch_began.clear();
+        self.cur_temp_input.touch_moved.clear();
+        self.cur_temp_input.touch_ended.clear();
+        self.cur_temp_input.points.retain(|_, p| matches!(p.phase, TouchPhase::Started | TouchPhase::Moved));
+        self.frame_events.clear();
+        swap(&mut self.frame_events, &mut self.event_queue);
 
         self.pressed_any_cur_frame = self.cur_frame_input.pressing.iter()
             .filter(|k| !self.last_frame_input.pressing.contains(k))
@@ -76,6 +531,61 @@ impl BakedInputs {
         keys.iter().any(|k| !self.last_frame_input.pressing.contains(k))
             && keys.iter().all(|k| self.cur_frame_input.pressing.contains(k))
     }
+
+    /// Get the mouse buttons pressed in this frame
+    pub fn mouse_pressed(&self) -> impl Iterator<Item=&MouseButton> {
+        self.cur_frame_input.mouse_pressing.iter()
+            .filter(|b| !self.last_frame_input.mouse_pressing.contains(b))
+    }
+
+    /// Get the mouse buttons released in this frame
+    pub fn mouse_released(&self) -> impl Iterator<Item=&MouseButton> {
+        self.last_frame_input.mouse_pressing.iter()
+            .filter(|b| !self.cur_frame_input.mouse_pressing.contains(b))
+    }
+
+    /// Get the cursor position in the 1600x900 design coordinates
+    pub fn cursor_design_position(&self) -> Option<[f32; 2]> {
+        self.cur_frame_input.cursor.map(|pos| [pos.x as f32 / self.size_scale[0], pos.y as f32 / self.size_scale[1]])
+    }
+
+    /// Is any binding of the action held in this frame
+    pub fn action_held(&self, action: Action) -> bool {
+        self.bindings.get(action).iter().any(|b| self.cur_frame_input.is_held(b, self.size_scale))
+    }
+
+    /// Is any binding of the action held in this frame but not in the last frame
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.bindings.get(action).iter()
+            .any(|b| self.cur_frame_input.is_held(b, self.size_scale) && !self.last_frame_input.is_held(b, self.size_scale))
+    }
+
+    /// Is any binding of the action held in the last frame but not in this frame
+    pub fn action_released(&self, action: Action) -> bool {
+        self.bindings.get(action).iter()
+            .any(|b| !self.cur_frame_input.is_held(b, self.size_scale) && self.last_frame_input.is_held(b, self.size_scale))
+    }
+
+    /// Count the presses of the action in this frame, each began touch in the regions counts once.
+    pub fn action_pressed_count(&self, action: Action) -> usize {
+        let cur = &self.cur_frame_input;
+        self.bindings.get(action).iter()
+            .map(|b| match b {
+                Binding::Touch(region) => cur.touch_began.iter()
+                    .filter(|p| region.contains(p.design_position(self.size_scale)))
+                    .count(),
+                _ => (cur.is_held(b, self.size_scale) && !self.last_frame_input.is_held(b, self.size_scale)) as usize
+            })
+            .sum()
+    }
+
+    /// Get a key or mouse button pressed in this frame, for rebinding, see [`RegionCapture`] for the touches.
+    pub fn pressed_binding(&self) -> Option<Binding> {
+        self.cur_frame_input.pressing.iter()
+            .find(|k| !self.last_frame_input.pressing.contains(k))
+            .map(|k| Binding::Key(*k))
+            .or_else(|| self.mouse_pressed().next().map(|b| Binding::Mouse(*b)))
+    }
 }
 
 impl RawInputData {
@@ -83,4 +593,14 @@ impl RawInputData {
     pub fn empty() -> Self {
         Self::default()
     }
-}
\ No newline at end of file
+
+    pub fn is_held(&self, binding: &Binding, size_scale: [f32; 2]) -> bool {
+        match binding {
+            Binding::Key(key) => self.pressing.contains(key),
+            Binding::Mouse(button) => self.mouse_pressing.contains(button),
+            Binding::Touch(region) => self.points.values()
+                .any(|p| region.contains(p.design_position(size_scale))),
+        }
+    }
+
+}
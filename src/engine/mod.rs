@@ -1,8 +1,18 @@
 pub use assets::*;
 pub use audio::*;
+pub use bot::*;
+pub use clock::*;
+pub use headless::*;
+pub use history::*;
 pub use input::*;
+pub use leaderboard::*;
+pub use network::*;
 pub use render::*;
+pub use replay::*;
+pub use settings::*;
 pub use state::*;
+pub use timestep::*;
+pub use tug::*;
 
 pub mod render;
 pub mod assets;
@@ -10,4 +20,15 @@ pub mod state;
 pub mod input;
 pub mod app;
 pub mod audio;
+pub mod bot;
+pub mod clock;
+pub mod headless;
+pub mod history;
+pub mod network;
+pub mod replay;
+pub mod settings;
+pub mod timestep;
+pub mod tug;
+
+pub use andy_clicker_leaderboard as leaderboard;
 
@@ -0,0 +1,148 @@
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::engine::network::PROTOCOL_VERSION;
+
+/// The clients listen on it for the hosts
+pub const DISCOVERY_PORT: u16 = 7879;
+/// The beacons go to the group and the broadcast address, every listener on the machine gets both
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 78, 79);
+
+const BEACON_INTERVAL: Duration = Duration::from_millis(500);
+/// Forget the host not heard for this long
+const HOST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The beacon the host broadcasts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Announce {
+    version: u32,
+    name: String,
+    /// The tcp port of the session
+    port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundHost {
+    pub name: String,
+    pub addr: SocketAddr,
+    pub last_seen: Duration,
+}
+
+/// Broadcasts the session on the local network.
+pub struct Beacon {
+    socket: Socket,
+    announce: Announce,
+    next: Duration,
+}
+
+impl Beacon {
+    pub fn new(name: String, port: u16) -> anyhow::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_broadcast(true)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into())?;
+        Ok(Self {
+            socket,
+            announce: Announce { version: PROTOCOL_VERSION, name, port },
+            next: Duration::ZERO,
+        })
+    }
+
+    /// Send the beacon if it is the time
+    pub fn poll(&mut self, now: Duration) {
+        if now < self.next {
+            return;
+        }
+        self.next = now + BEACON_INTERVAL;
+        let data = match serde_json::to_vec(&self.announce) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("Serialize the beacon failed for {:?}", e);
+                return;
+            }
+        };
+        // the broadcast does not loop back everywhere, the group does on the default and the loopback interfaces
+        if let Err(e) = self.socket.send_to(&data, &SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)).into()) {
+            log::debug!("Send the beacon to the broadcast failed for {:?}", e);
+        }
+        for interface in [Ipv4Addr::UNSPECIFIED, Ipv4Addr::LOCALHOST] {
+            let result = self.socket.set_multicast_if_v4(&interface)
+                .and_then(|_| self.socket.send_to(&data, &SocketAddr::from((DISCOVERY_GROUP, DISCOVERY_PORT)).into()));
+            if let Err(e) = result {
+                log::debug!("Send the beacon to the group on {} failed for {:?}", interface, e);
+            }
+        }
+    }
+}
+
+/// Listens for the beacons and keeps the hosts heard lately.
+///
+/// The port is shared, so every process on the machine can listen.
+pub struct Discovery {
+    socket: UdpSocket,
+    hosts: Vec<FoundHost>,
+}
+
+impl Discovery {
+    pub fn new() -> anyhow::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).into())?;
+        for interface in [Ipv4Addr::UNSPECIFIED, Ipv4Addr::LOCALHOST] {
+            if let Err(e) = socket.join_multicast_v4(&DISCOVERY_GROUP, &interface) {
+                log::debug!("Join the discovery group on {} failed for {:?}", interface, e);
+            }
+        }
+        let socket = UdpSocket::from(socket);
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            hosts: vec![],
+        })
+    }
+
+    pub fn poll(&mut self, now: Duration) {
+        let mut buf = [0; 1024];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, from)) => {
+                    let announce: Announce = match serde_json::from_slice(&buf[..n]) {
+                        Ok(x) => x,
+                        Err(e) => {
+                            log::debug!("Bad beacon from {} for {:?}", from, e);
+                            continue;
+                        }
+                    };
+                    if announce.version != PROTOCOL_VERSION {
+                        continue;
+                    }
+                    let addr = SocketAddr::new(from.ip(), announce.port);
+                    match self.hosts.iter_mut().find(|x| x.addr == addr) {
+                        Some(host) => {
+                            host.name = announce.name;
+                            host.last_seen = now;
+                        }
+                        None => self.hosts.push(FoundHost { name: announce.name, addr, last_seen: now }),
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Receive the beacons failed for {:?}", e);
+                    break;
+                }
+            }
+        }
+        self.hosts.retain(|x| now.saturating_sub(x.last_seen) < HOST_TIMEOUT);
+    }
+
+    pub fn hosts(&self) -> &[FoundHost] {
+        &self.hosts
+    }
+}
@@ -0,0 +1,11 @@
+pub use discovery::*;
+pub use protocol::*;
+pub use rollback::*;
+pub use session::*;
+pub use spectate::*;
+
+pub mod discovery;
+pub mod protocol;
+pub mod rollback;
+pub mod session;
+pub mod spectate;
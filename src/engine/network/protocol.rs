@@ -0,0 +1,127 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{TugModel, TugSnapshot};
+use crate::engine::network::{LogClick, MatchInfo, TickClick};
+
+/// Bump it when the messages change, the peers of other versions are refused
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// The connection fails if more bytes than it are waiting to be sent
+const MAX_BACKLOG: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub name: String,
+    pub color: [f32; 3],
+}
+
+/// The messages between the host and the client, the times are in the host clock.
+///
+/// The spectators get the match log of the host in the host local clock.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    Hello { version: u32, peer: PeerInfo },
+    Welcome { peer: PeerInfo },
+    Ping { client_time: Duration },
+    Pong { client_time: Duration, host_time: Duration },
+    /// The sender changed its color or readiness in the lobby
+    Lobby { peer: PeerInfo, ready: bool },
+    /// The match counts down from the start time, the clicks apply `input_delay` ticks later
+    Start { start_time: Duration, win_target: f32, physics: TugModel, input_delay: u64 },
+    /// The sender's new clicks, all its clicks before the tick `until` are sent
+    Inputs { until: u64, clicks: Vec<TickClick> },
+    /// The checksum of the sender's sim with all the clicks before the tick
+    Checksum { tick: u64, sum: u64 },
+    /// The spectator wants the matches
+    Watch { version: u32 },
+    /// The match to watch from now, the go time is `go_in` later
+    Snapshot { info: MatchInfo, go_in: Duration, sim: TugSnapshot },
+    /// The new clicks of the match log, the log is complete before the tick `until`
+    Events { until: u64, clicks: Vec<LogClick> },
+    Leave,
+}
+
+/// The messages over the tcp stream, one json per line.
+///
+/// Sending never blocks, the data the stream does not take yet waits in the backlog.
+pub struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    /// Waiting to be written to the stream
+    backlog: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> anyhow::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            buf: vec![],
+            backlog: vec![],
+        })
+    }
+
+    /// Queue the message and write as much as the stream takes,
+    /// fails if the peer is not reading for too long
+    pub fn send(&mut self, msg: &Message) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.backlog, msg)?;
+        self.backlog.push(b'\n');
+        self.flush()
+    }
+
+    /// Write the backlog as far as possible without blocking
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        let mut written = 0;
+        while written < self.backlog.len() {
+            match self.stream.write(&self.backlog[written..]) {
+                Ok(0) => return Err(anyhow!("The connection is closed")),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.backlog.drain(..written);
+        if self.backlog.len() > MAX_BACKLOG {
+            return Err(anyhow!("The peer is not reading, {} bytes are waiting", self.backlog.len()));
+        }
+        Ok(())
+    }
+
+    /// The bytes not written yet
+    pub fn backlog(&self) -> usize {
+        self.backlog.len()
+    }
+
+    /// Get the messages arrived without blocking
+    pub fn recv(&mut self) -> anyhow::Result<Vec<Message>> {
+        let mut chunk = [0; 4096];
+        let mut closed = false;
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let mut ret = vec![];
+        while let Some(i) = self.buf.iter().position(|x| *x == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            ret.push(serde_json::from_slice(&line[..i])?);
+        }
+        // the messages before the close are still delivered, the next call fails
+        if closed && ret.is_empty() {
+            return Err(anyhow!("The connection is closed"));
+        }
+        Ok(ret)
+    }
+}
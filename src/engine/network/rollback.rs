@@ -0,0 +1,466 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::TugSim;
+use crate::engine::network::{LogClick, Message};
+
+/// The ticks of the network game per second, counted from the go time in the host clock
+pub const ROLLBACK_HZ: u32 = 240;
+/// Keep the snapshots of this many ticks, the inputs later than it can not be rolled back exactly
+const MAX_ROLLBACK: usize = 240;
+/// Predict the remote player keeps the last interval for this long after the last click
+const PREDICT_LIMIT: Duration = Duration::from_millis(250);
+/// Tell the peer the input frontier at least this often
+const SEND_INTERVAL: Duration = Duration::from_millis(8);
+/// Compare the confirmed sim with the peer every this many ticks
+const CHECK_TICKS: u64 = ROLLBACK_HZ as u64;
+/// Keep this many checksums to compare
+const MAX_CHECKS: usize = 16;
+
+/// A click scheduled for the tick, the time is in the host clock
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TickClick {
+    pub tick: u64,
+    pub time: Duration,
+    pub synthetic: bool,
+}
+
+/// Simulates the match with the local clicks and the remote clicks predicted,
+/// rolls back and simulates again when the confirmed remote clicks are not the predicted ones.
+///
+/// Both peers run the same ticks with the same clicks so they end with the same progress,
+/// they exchange the checksums of the confirmed sim to tell if they did not.
+pub struct Rollback {
+    go_time: Duration,
+    /// The ticks the local clicks are delayed
+    delay: u64,
+    local: usize,
+    remote: usize,
+    /// The next tick to simulate
+    tick: u64,
+    local_clicks: Vec<TickClick>,
+    /// The local clicks before the tick are sent, the later clicks are scheduled from it
+    local_until: u64,
+    outbox: Vec<TickClick>,
+    last_send: Option<Duration>,
+    remote_clicks: Vec<TickClick>,
+    /// The remote clicks before the tick are all arrived
+    remote_until: u64,
+    /// The remote clicks guessed for the ticks simulated from `remote_until`
+    predicted: Vec<TickClick>,
+    /// The sim at the start of the ticks
+    snapshots: VecDeque<(u64, TugSim)>,
+    /// The sim of the ticks before [`Rollback::settled_tick`], the rollbacks older than the snapshots start from it
+    confirmed: TugSim,
+    /// The checksums of the confirmed sim at the ticks
+    checks: VecDeque<(u64, u64)>,
+    /// The peer checksums of the ticks not confirmed here yet
+    peer_checks: VecDeque<(u64, u64)>,
+    unsent_checks: Vec<(u64, u64)>,
+    /// The first tick the peers disagree on
+    desync: Option<u64>,
+    rollbacks: usize,
+}
+
+impl Rollback {
+    /// `go_time` is in the host clock, `local` and `remote` are the player indexes in the sim
+    pub fn new(sim: &TugSim, go_time: Duration, delay: u64, local: usize, remote: usize) -> Self {
+        Self {
+            go_time,
+            delay,
+            local,
+            remote,
+            tick: 0,
+            local_clicks: vec![],
+            local_until: 0,
+            outbox: vec![],
+            last_send: None,
+            remote_clicks: vec![],
+            remote_until: 0,
+            predicted: vec![],
+            snapshots: VecDeque::new(),
+            confirmed: sim.clone(),
+            checks: VecDeque::new(),
+            peer_checks: VecDeque::new(),
+            unsent_checks: vec![],
+            desync: None,
+            rollbacks: 0,
+        }
+    }
+
+    /// The ticks for the milliseconds of input delay
+    pub fn delay_ticks(ms: u64) -> u64 {
+        ms * ROLLBACK_HZ as u64 / 1000
+    }
+
+    /// The tick the host time is in, 0 before go
+    pub fn tick_of(&self, time: Duration) -> u64 {
+        (time.saturating_sub(self.go_time).as_secs_f64() * ROLLBACK_HZ as f64) as u64
+    }
+
+    /// The next tick to simulate
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// How many times the sim went back
+    pub fn rollbacks(&self) -> usize {
+        self.rollbacks
+    }
+
+    /// The tick the confirmed sims of the peers differ at, the match can not go on
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    /// The sim has ended and no remote click can change it anymore
+    pub fn is_settled(&self, sim: &TugSim) -> bool {
+        sim.is_over() && self.remote_until >= self.tick
+    }
+
+    /// The local player clicked at the host time, it applies after the input delay
+    pub fn local_click(&mut self, time: Duration, synthetic: bool) {
+        // the ticks simulated or sent already can not change
+        let click = TickClick { tick: (self.tick_of(time) + self.delay).max(self.local_until).max(self.tick), time, synthetic };
+        self.local_clicks.push(click);
+        self.outbox.push(click);
+    }
+
+    /// The clicks with the frontier and the checksums to send to the peer
+    pub fn outgoing(&mut self, now: Duration) -> Vec<Message> {
+        let mut ret: Vec<Message> = self.unsent_checks.drain(..).map(|(tick, sum)| Message::Checksum { tick, sum }).collect();
+        let until = (self.tick_of(now) + self.delay).max(self.local_until);
+        let due = self.last_send.map(|x| now >= x + SEND_INTERVAL).unwrap_or(true);
+        if !self.outbox.is_empty() || (until != self.local_until && due) {
+            self.last_send = Some(now);
+            self.local_until = until;
+            ret.push(Message::Inputs { until, clicks: std::mem::take(&mut self.outbox) });
+        }
+        ret
+    }
+
+    /// The peer sent its clicks, go back to the first tick predicted wrong.
+    ///
+    /// The predicted clicks are compared with the times too, as the click strength comes from the time,
+    /// so the ticks with the remote clicks always roll back and only the empty ticks are predicted right.
+    pub fn remote_inputs(&mut self, sim: &mut TugSim, until: u64, clicks: Vec<TickClick>) {
+        self.remote_clicks.extend(clicks);
+        if until <= self.remote_until {
+            return;
+        }
+        let range = self.remote_until..until.min(self.tick);
+        let confirmed = self.remote_clicks.iter().filter(|x| range.contains(&x.tick));
+        let predicted = self.predicted.iter().filter(|x| range.contains(&x.tick));
+        let wrong = !confirmed.clone().eq(predicted.clone());
+        let first = confirmed.chain(predicted).map(|x| x.tick).min();
+        self.remote_until = until;
+        self.predicted.retain(|x| x.tick >= until);
+        if wrong {
+            self.rollback(sim, first.unwrap());
+        }
+        self.confirm();
+    }
+
+    /// The peer checksum of its confirmed sim at the tick
+    pub fn remote_checksum(&mut self, tick: u64, sum: u64) {
+        match self.checks.iter().find(|x| x.0 == tick) {
+            Some(&(_, own)) => self.compare(tick, own, sum),
+            None if tick > self.confirmed.ticks() => {
+                self.peer_checks.push_back((tick, sum));
+                if self.peer_checks.len() > MAX_CHECKS {
+                    self.peer_checks.pop_front();
+                }
+            }
+            // too old to compare
+            None => {}
+        }
+    }
+
+    fn compare(&mut self, tick: u64, own: u64, peer: u64) {
+        if own != peer && self.desync.is_none() {
+            log::warn!("Out of sync with the peer at tick {}", tick);
+            self.desync = Some(tick);
+        }
+    }
+
+    /// Go back to the start of the tick, from the confirmed sim if the snapshots do not reach
+    fn rollback(&mut self, sim: &mut TugSim, tick: u64) {
+        if tick >= self.tick {
+            return;
+        }
+        match self.snapshots.iter().position(|(t, _)| *t == tick) {
+            Some(i) => {
+                *sim = self.snapshots[i].1.clone();
+                self.snapshots.truncate(i);
+            }
+            None => {
+                log::info!("The click at tick {} is older than the snapshots, going back to the confirmed tick {}", tick, self.confirmed.ticks());
+                *sim = self.confirmed.clone();
+                self.snapshots.clear();
+            }
+        }
+        self.tick = sim.ticks();
+        let tick = self.tick;
+        self.predicted.retain(|x| x.tick < tick);
+        self.rollbacks += 1;
+    }
+
+    /// Simulate the confirmed sim to the settled tick and take its checksums
+    // `is_multiple_of` is newer than the toolchains we build on
+    #[allow(clippy::manual_is_multiple_of)]
+    fn confirm(&mut self) {
+        while self.confirmed.ticks() < self.settled_tick() {
+            let tick = self.confirmed.ticks();
+            let clicks = self.tick_clicks(tick, self.remote_clicks.iter().filter(|x| x.tick == tick).copied().collect());
+            simulate(&mut self.confirmed, clicks);
+            let tick = self.confirmed.ticks();
+            if tick % CHECK_TICKS == 0 {
+                let sum = self.confirmed.checksum();
+                self.unsent_checks.push((tick, sum));
+                if let Some(i) = self.peer_checks.iter().position(|x| x.0 == tick) {
+                    let (_, peer) = self.peer_checks.remove(i).unwrap();
+                    self.compare(tick, sum, peer);
+                }
+                self.checks.push_back((tick, sum));
+                if self.checks.len() > MAX_CHECKS {
+                    self.checks.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Guess the remote clicks of the tick by the last interval
+    fn predict(&self, tick: u64) -> Vec<TickClick> {
+        let mut confirmed = self.remote_clicks.iter().filter(|x| x.tick < self.remote_until);
+        let (last, prev) = match (confirmed.next_back(), confirmed.next_back()) {
+            (Some(last), Some(prev)) => (last, prev),
+            _ => return vec![],
+        };
+        let interval = last.time.saturating_sub(prev.time);
+        if interval.is_zero() {
+            return vec![];
+        }
+        let delay = last.tick - self.tick_of(last.time).min(last.tick);
+        let mut ret = vec![];
+        let mut time = last.time + interval;
+        while time <= last.time + PREDICT_LIMIT {
+            let predicted = self.tick_of(time) + delay;
+            if predicted > tick {
+                break;
+            }
+            if predicted == tick {
+                ret.push(TickClick { tick, time, synthetic: last.synthetic });
+            }
+            time += interval;
+        }
+        ret
+    }
+
+    /// The clicks of the tick with the player indexes in the order they apply
+    fn tick_clicks(&self, tick: u64, remote: Vec<TickClick>) -> Vec<(usize, TickClick)> {
+        let mut clicks: Vec<(usize, TickClick)> = self.local_clicks.iter().filter(|x| x.tick == tick)
+            .map(|x| (self.local, *x))
+            .chain(remote.into_iter().map(|x| (self.remote, x)))
+            .collect();
+        clicks.sort_by_key(|(i, x)| (x.time, *i));
+        clicks
+    }
+
+    /// The ticks before it have all the clicks and will not roll back
+    pub fn settled_tick(&self) -> u64 {
+        self.remote_until.min(self.tick)
+    }
+
+    /// The clicks of the settled ticks from the tick, for the spectators
+    pub fn settled_clicks(&self, from: u64) -> Vec<LogClick> {
+        (from..self.settled_tick())
+            .flat_map(|tick| self.tick_clicks(tick, self.remote_clicks.iter().filter(|x| x.tick == tick).copied().collect()))
+            .map(|(player, x)| LogClick { tick: x.tick, player, time: x.time, synthetic: x.synthetic })
+            .collect()
+    }
+
+    fn step(&mut self, sim: &mut TugSim) {
+        let tick = self.tick;
+        self.snapshots.push_back((tick, sim.clone()));
+        if self.snapshots.len() > MAX_ROLLBACK {
+            self.snapshots.pop_front();
+        }
+        let remote = if tick < self.remote_until {
+            self.remote_clicks.iter().filter(|x| x.tick == tick).copied().collect()
+        } else {
+            let predicted = self.predict(tick);
+            self.predicted.extend_from_slice(&predicted);
+            predicted
+        };
+        simulate(sim, self.tick_clicks(tick, remote));
+        self.tick += 1;
+    }
+
+    /// Simulate the ticks up to the host time, the match stops when the peers are out of sync
+    pub fn advance(&mut self, sim: &mut TugSim, now: Duration) {
+        if self.desync.is_some() {
+            return;
+        }
+        let target = self.tick_of(now);
+        while self.tick < target {
+            self.step(sim);
+        }
+        self.confirm();
+    }
+}
+
+/// Apply the clicks and step a tick
+fn simulate(sim: &mut TugSim, clicks: Vec<(usize, TickClick)>) {
+    for (i, click) in clicks {
+        sim.click(i, click.time, click.synthetic);
+    }
+    sim.step(1.0 / ROLLBACK_HZ as f32);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+    use crate::engine::{Side, TugModel};
+
+    const GO: u64 = 1000;
+    const FRAME: u64 = 4;
+
+    fn ms(x: u64) -> Duration {
+        Duration::from_millis(x)
+    }
+
+    /// Two peers playing over a wire, the times are in milliseconds of the host clock
+    struct Match {
+        sims: [TugSim; 2],
+        peers: [Rollback; 2],
+        /// The messages with the arrival time and the receiver
+        wire: Vec<(Duration, usize, Message)>,
+        latency: u64,
+        /// The messages to the client sent in the range arrive at its end
+        stall: Range<u64>,
+    }
+
+    impl Match {
+        fn new(delay: u64, latency: u64) -> Self {
+            let sim = TugSim::new(TugModel::Friction, 1000.0, [Side::Left, Side::Right]);
+            Self {
+                peers: [Rollback::new(&sim, ms(GO), delay, 0, 1), Rollback::new(&sim, ms(GO), delay, 1, 0)],
+                sims: [sim.clone(), sim],
+                wire: vec![],
+                latency,
+                stall: 0..0,
+            }
+        }
+
+        fn deliver(&mut self, now: Duration) {
+            let (arrived, wire) = std::mem::take(&mut self.wire).into_iter().partition(|x| x.0 <= now);
+            self.wire = wire;
+            for (_, to, msg) in arrived {
+                match msg {
+                    Message::Inputs { until, clicks } => self.peers[to].remote_inputs(&mut self.sims[to], until, clicks),
+                    Message::Checksum { tick, sum } => self.peers[to].remote_checksum(tick, sum),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        fn send(&mut self, t: u64) {
+            for from in 0..2 {
+                let mut arrival = t + self.latency;
+                if from == 0 && self.stall.contains(&t) {
+                    arrival = self.stall.end;
+                }
+                // the stream keeps the order
+                let last = self.wire.iter().filter(|x| x.1 != from).map(|x| x.0).max().unwrap_or_default();
+                for msg in self.peers[from].outgoing(ms(t)) {
+                    self.wire.push((ms(arrival).max(last), 1 - from, msg));
+                }
+            }
+        }
+
+        /// Both click at uneven rates in the time range, then all the messages arrive
+        fn play(&mut self, range: Range<u64>) {
+            let end = range.end;
+            for t in range.step_by(FRAME as usize) {
+                if t % 83 < FRAME {
+                    self.peers[0].local_click(ms(t), false);
+                }
+                if t % (97 + (t / 500) % 3 * 10) < FRAME {
+                    self.peers[1].local_click(ms(t), t % 7 == 0);
+                }
+                self.deliver(ms(t));
+                for i in 0..2 {
+                    self.peers[i].advance(&mut self.sims[i], ms(t));
+                }
+                self.send(t);
+            }
+            self.send(end);
+            self.deliver(Duration::MAX);
+            for i in 0..2 {
+                self.peers[i].advance(&mut self.sims[i], ms(end));
+            }
+            self.send(end);
+            self.deliver(Duration::MAX);
+        }
+
+        fn assert_agree(&self) {
+            assert_eq!(self.sims[0].snapshot(), self.sims[1].snapshot());
+            assert!(self.sims[0].players.iter().all(|x| x.clicks.len() > 10));
+            assert_eq!(self.peers[0].desync(), None);
+            assert_eq!(self.peers[1].desync(), None);
+            // the same as the sim never rolled back
+            let mut replayed = TugSim::new(TugModel::Friction, 1000.0, [Side::Left, Side::Right]);
+            let mut clicks = self.peers[0].settled_clicks(0).into_iter().peekable();
+            while replayed.ticks() < self.sims[0].ticks() {
+                while let Some(click) = clicks.next_if(|x| x.tick == replayed.ticks()) {
+                    replayed.click(click.player, click.time, click.synthetic);
+                }
+                replayed.step(1.0 / ROLLBACK_HZ as f32);
+            }
+            assert_eq!(replayed.snapshot(), self.sims[0].snapshot());
+        }
+    }
+
+    #[test]
+    fn peers_agree_at_any_latency() {
+        for latency in [0, 5, 30, 120] {
+            let mut game = Match::new(3, latency);
+            game.play(GO..6000);
+            game.assert_agree();
+            // the delay hides the latency
+            if latency <= 5 {
+                assert_eq!(game.peers[0].rollbacks() + game.peers[1].rollbacks(), 0);
+            } else {
+                assert!(game.peers[1].rollbacks() > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn stall_longer_than_the_snapshots() {
+        let mut game = Match::new(3, 10);
+        game.stall = 2000..3500;
+        game.play(GO..6000);
+        game.assert_agree();
+    }
+
+    #[test]
+    fn checksums_catch_desync() {
+        let mut game = Match::new(3, 10);
+        game.play(GO..3000);
+        game.assert_agree();
+        assert!(!game.peers[0].checks.is_empty());
+        // the client sim goes wrong
+        game.peers[1].confirmed.players[0].force += 1.0;
+        game.play(3000..5000);
+        assert!(game.peers[0].desync().is_some());
+        assert!(game.peers[1].desync().is_some());
+        let ticks = game.sims[0].ticks();
+        game.peers[0].advance(&mut game.sims[0], ms(9000));
+        assert_eq!(game.sims[0].ticks(), ticks);
+    }
+}
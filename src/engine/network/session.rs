@@ -0,0 +1,237 @@
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::engine::network::{Connection, Message, PeerInfo, PROTOCOL_VERSION};
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const PING_INTERVAL: Duration = Duration::from_millis(200);
+/// The client is synced after this many pongs
+const SYNC_SAMPLES: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetRole {
+    Host,
+    Client,
+}
+
+/// One host and one client over tcp.
+///
+/// The host clock is the match clock, the client measures the offset to it by the pings.
+pub struct NetSession {
+    role: NetRole,
+    listener: Option<TcpListener>,
+    conn: Option<Connection>,
+    local: PeerInfo,
+    peer: Option<PeerInfo>,
+    local_ready: bool,
+    peer_ready: bool,
+    /// The host clock minus the local clock in seconds
+    offset: f64,
+    /// The round trip of the sample the offset is from
+    rtt: Option<f64>,
+    samples: usize,
+    next_ping: Duration,
+    /// The messages for the game, the session ones are handled already
+    inbox: Vec<Message>,
+    error: Option<String>,
+}
+
+impl NetSession {
+    fn new(role: NetRole, local: PeerInfo) -> Self {
+        Self {
+            role,
+            listener: None,
+            conn: None,
+            local,
+            peer: None,
+            local_ready: false,
+            peer_ready: false,
+            offset: 0.0,
+            rtt: None,
+            samples: 0,
+            next_ping: Duration::ZERO,
+            inbox: vec![],
+            error: None,
+        }
+    }
+
+    /// Listen on all interfaces for the client
+    pub fn host(port: u16, local: PeerInfo) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let mut ret = Self::new(NetRole::Host, local);
+        ret.listener = Some(listener);
+        Ok(ret)
+    }
+
+    pub fn connect(addr: SocketAddr, local: PeerInfo) -> anyhow::Result<Self> {
+        let mut conn = Connection::new(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?)?;
+        conn.send(&Message::Hello { version: PROTOCOL_VERSION, peer: local.clone() })?;
+        let mut ret = Self::new(NetRole::Client, local);
+        ret.conn = Some(conn);
+        Ok(ret)
+    }
+
+    pub fn role(&self) -> NetRole {
+        self.role
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.as_ref().and_then(|x| x.local_addr().ok())
+    }
+
+    pub fn local(&self) -> &PeerInfo {
+        &self.local
+    }
+
+    /// The peer said hello
+    pub fn peer(&self) -> Option<&PeerInfo> {
+        self.peer.as_ref()
+    }
+
+    pub fn peer_ready(&self) -> bool {
+        self.peer_ready
+    }
+
+    /// Both players are ready in the lobby
+    pub fn all_ready(&self) -> bool {
+        self.is_synced() && self.local_ready && self.peer_ready
+    }
+
+    /// Tell the peer if the local player changed in the lobby
+    pub fn set_local(&mut self, local: PeerInfo, ready: bool) {
+        if local != self.local || ready != self.local_ready {
+            self.local = local.clone();
+            self.local_ready = ready;
+            self.send(&Message::Lobby { peer: local, ready });
+        }
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt.map(Duration::from_secs_f64)
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_some() && self.peer.is_some()
+    }
+
+    /// Both sides know each other and the client knows the host clock
+    pub fn is_synced(&self) -> bool {
+        self.is_connected() && (self.role == NetRole::Host || self.samples >= SYNC_SAMPLES)
+    }
+
+    pub fn to_host(&self, local: Duration) -> Duration {
+        Duration::from_secs_f64((local.as_secs_f64() + self.offset).max(0.0))
+    }
+
+    pub fn from_host(&self, host: Duration) -> Duration {
+        Duration::from_secs_f64((host.as_secs_f64() - self.offset).max(0.0))
+    }
+
+    /// Send to the peer, the failure disconnects
+    pub fn send(&mut self, msg: &Message) {
+        if let Some(conn) = &mut self.conn {
+            if let Err(e) = conn.send(msg) {
+                self.disconnect(e);
+            }
+        }
+    }
+
+    fn disconnect(&mut self, e: anyhow::Error) {
+        log::warn!("Disconnected from {:?} for {:?}", self.peer, e);
+        self.conn = None;
+        self.peer = None;
+        self.peer_ready = false;
+        self.samples = 0;
+        self.error = Some(e.to_string());
+    }
+
+    /// Accept the client, exchange the hellos and the pings, call it every frame.
+    pub fn poll(&mut self, now: Duration) {
+        if let (Some(listener), None) = (&self.listener, &self.conn) {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    log::info!("Accepted {}", addr);
+                    match Connection::new(stream) {
+                        Ok(conn) => {
+                            self.conn = Some(conn);
+                            self.error = None;
+                        }
+                        Err(e) => log::warn!("Accept {} failed for {:?}", addr, e),
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => log::warn!("Accept failed for {:?}", e),
+            }
+        }
+        let msgs = match self.conn.as_mut().map(|x| x.flush().and_then(|_| x.recv())) {
+            Some(Ok(msgs)) => msgs,
+            Some(Err(e)) => {
+                self.disconnect(e);
+                return;
+            }
+            None => return,
+        };
+        for msg in msgs {
+            match msg {
+                Message::Hello { version, peer } => {
+                    if version != PROTOCOL_VERSION {
+                        self.disconnect(anyhow!("The peer protocol version {} is not {}", version, PROTOCOL_VERSION));
+                        return;
+                    }
+                    self.peer = Some(peer);
+                    let welcome = Message::Welcome { peer: self.local.clone() };
+                    self.send(&welcome);
+                    if self.local_ready {
+                        self.send(&Message::Lobby { peer: self.local.clone(), ready: true });
+                    }
+                }
+                Message::Welcome { peer } => self.peer = Some(peer),
+                Message::Lobby { peer, ready } => {
+                    self.peer = Some(peer);
+                    self.peer_ready = ready;
+                }
+                Message::Ping { client_time } => self.send(&Message::Pong { client_time, host_time: now }),
+                Message::Pong { client_time, host_time } => {
+                    let rtt = now.saturating_sub(client_time).as_secs_f64();
+                    // the sample with the shortest round trip is the most accurate
+                    if self.rtt.map(|x| rtt <= x).unwrap_or(true) {
+                        self.rtt = Some(rtt);
+                        self.offset = host_time.as_secs_f64() + rtt / 2.0 - now.as_secs_f64();
+                    }
+                    self.samples += 1;
+                }
+                Message::Leave => {
+                    self.disconnect(anyhow!("The peer left"));
+                    return;
+                }
+                msg => self.inbox.push(msg),
+            }
+        }
+        if self.role == NetRole::Client && self.conn.is_some() && now >= self.next_ping {
+            self.next_ping = now + PING_INTERVAL;
+            self.send(&Message::Ping { client_time: now });
+        }
+    }
+
+    /// Take the game messages arrived
+    pub fn take_messages(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.inbox)
+    }
+
+    /// Tell the peer and close
+    pub fn leave(&mut self) {
+        self.send(&Message::Leave);
+        self.conn = None;
+        self.peer = None;
+        self.peer_ready = false;
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::VecDeque;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{PlayerSettings, TugSim};
+use crate::engine::network::{Connection, Message, PROTOCOL_VERSION};
+
+/// The spectators connect to it
+pub const SPECTATE_PORT: u16 = 7880;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// The log may complete at most this far ahead of the sim the spectator has
+const MAX_AHEAD: Duration = Duration::from_secs(60);
+
+/// A click of the match log, it applies before the step `tick` of the sim
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogClick {
+    pub tick: u64,
+    pub player: usize,
+    pub time: Duration,
+    pub synthetic: bool,
+}
+
+/// What the spectators need to show the match besides the sim
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchInfo {
+    /// The players without the bindings, in the sim order
+    pub players: Vec<PlayerSettings>,
+    /// The seconds of one sim step
+    pub dt: f32,
+}
+
+/// Applies the match log to the sim
+#[derive(Default)]
+struct Replay {
+    events: VecDeque<LogClick>,
+    /// The log is complete before the tick
+    until: u64,
+    dt: f32,
+    /// How many players the sim has
+    players: usize,
+}
+
+impl Replay {
+    fn new(info: &MatchInfo, sim: &TugSim) -> Self {
+        Self {
+            events: VecDeque::new(),
+            until: sim.ticks(),
+            dt: info.dt,
+            players: sim.players.len(),
+        }
+    }
+
+    /// Check the snapshot from the network can be shown
+    fn check(info: &MatchInfo, sim: &TugSim) -> anyhow::Result<()> {
+        if !(info.dt > 0.0 && info.dt <= 1.0) {
+            return Err(anyhow!("Bad step of {}s", info.dt));
+        }
+        if !(sim.win_target() > 0.0 && sim.win_target().is_finite()) {
+            return Err(anyhow!("Bad win target {}", sim.win_target()));
+        }
+        if info.players.len() != sim.players.len() || info.players.iter().zip(&sim.players).any(|(a, b)| a.side != b.side) {
+            return Err(anyhow!("The players do not match the sim"));
+        }
+        Ok(())
+    }
+
+    /// Add the log from the network, it must be for the players and not too far ahead
+    fn extend(&mut self, until: u64, clicks: Vec<LogClick>) -> anyhow::Result<()> {
+        if self.players == 0 {
+            // no match yet
+            return Ok(());
+        }
+        let max_until = self.until + (MAX_AHEAD.as_secs_f32() / self.dt) as u64;
+        if until > max_until {
+            return Err(anyhow!("The log until {} is too far ahead of {}", until, self.until));
+        }
+        if let Some(click) = clicks.iter().find(|x| x.player >= self.players || x.tick > until.max(self.until)) {
+            return Err(anyhow!("Bad click {:?}", click));
+        }
+        self.events.extend(clicks);
+        self.until = self.until.max(until);
+        Ok(())
+    }
+
+    fn advance(&mut self, sim: &mut TugSim) {
+        while sim.ticks() < self.until {
+            while let Some(click) = self.events.front().filter(|x| x.tick <= sim.ticks()).copied() {
+                self.events.pop_front();
+                sim.click(click.player, click.time, click.synthetic);
+            }
+            sim.step(self.dt);
+        }
+    }
+}
+
+struct Watcher {
+    conn: Connection,
+    /// It said watch with our version
+    ready: bool,
+}
+
+/// Streams the matches played here to the spectators.
+///
+/// It keeps the sim the spectators see, the late ones start from its snapshot.
+pub struct Broadcast {
+    listener: TcpListener,
+    watchers: Vec<Watcher>,
+    info: Option<MatchInfo>,
+    sim: Option<TugSim>,
+    replay: Replay,
+    /// The go time of the match in the local clock
+    go_time: Duration,
+}
+
+impl Broadcast {
+    /// Listen on all interfaces for the spectators
+    pub fn new(port: u16) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            watchers: vec![],
+            info: None,
+            sim: None,
+            replay: Default::default(),
+            go_time: Duration::ZERO,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// How many spectators are watching
+    pub fn watchers(&self) -> usize {
+        self.watchers.iter().filter(|x| x.ready).count()
+    }
+
+    /// The messages for the spectator joining now
+    fn snapshot(&self, now: Duration) -> Vec<Message> {
+        match (&self.info, &self.sim) {
+            (Some(info), Some(sim)) => vec![
+                Message::Snapshot { info: info.clone(), go_in: self.go_time.saturating_sub(now), sim: sim.snapshot() },
+                Message::Events { until: self.replay.until, clicks: self.replay.events.iter().copied().collect() },
+            ],
+            _ => vec![],
+        }
+    }
+
+    /// Send to the ready spectators without blocking, drop the failed ones and the ones not reading
+    fn send_all(&mut self, msgs: &[Message]) {
+        self.watchers.retain_mut(|watcher| {
+            !watcher.ready || msgs.iter().try_for_each(|msg| watcher.conn.send(msg))
+                .map_err(|e| log::info!("The spectator left for {:?}", e))
+                .is_ok()
+        });
+    }
+
+    /// A new match starts from the sim, `go_time` is in the local clock
+    pub fn begin(&mut self, info: MatchInfo, sim: TugSim, go_time: Duration, now: Duration) {
+        self.replay = Replay::new(&info, &sim);
+        self.info = Some(info);
+        self.sim = Some(sim);
+        self.go_time = go_time;
+        let msgs = self.snapshot(now);
+        self.send_all(&msgs);
+    }
+
+    /// The clicks of the match, the log is complete before the tick `until`
+    pub fn publish(&mut self, until: u64, clicks: Vec<LogClick>) {
+        let sim = match &mut self.sim {
+            Some(sim) => sim,
+            None => return,
+        };
+        if until <= self.replay.until && clicks.is_empty() {
+            return;
+        }
+        self.replay.events.extend(clicks.iter().copied());
+        self.replay.until = self.replay.until.max(until);
+        self.replay.advance(sim);
+        let msg = Message::Events { until: self.replay.until, clicks };
+        self.send_all(&[msg]);
+    }
+
+    /// Accept the spectators, call it every frame
+    pub fn poll(&mut self, now: Duration) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => match Connection::new(stream) {
+                    Ok(conn) => {
+                        log::info!("Spectator {} connected", addr);
+                        self.watchers.push(Watcher { conn, ready: false });
+                    }
+                    Err(e) => log::warn!("Accept the spectator {} failed for {:?}", addr, e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Accept the spectator failed for {:?}", e);
+                    break;
+                }
+            }
+        }
+        let snapshot = self.snapshot(now);
+        self.watchers.retain_mut(|watcher| {
+            let msgs = match watcher.conn.flush().and_then(|_| watcher.conn.recv()) {
+                Ok(msgs) => msgs,
+                Err(e) => {
+                    log::info!("The spectator left for {:?}", e);
+                    return false;
+                }
+            };
+            for msg in msgs {
+                match msg {
+                    Message::Watch { version } if version == PROTOCOL_VERSION && !watcher.ready => {
+                        watcher.ready = true;
+                        if let Err(e) = snapshot.iter().try_for_each(|msg| watcher.conn.send(msg)) {
+                            log::info!("The spectator left for {:?}", e);
+                            return false;
+                        }
+                    }
+                    Message::Watch { version } if version != PROTOCOL_VERSION => {
+                        log::info!("The spectator protocol version {} is not {}", version, PROTOCOL_VERSION);
+                        return false;
+                    }
+                    Message::Leave => return false,
+                    _ => {}
+                }
+            }
+            true
+        });
+    }
+}
+
+/// The match the spectator starts to show
+pub struct WatchedMatch {
+    pub info: MatchInfo,
+    pub sim: TugSim,
+    /// The go time in the local clock with the delay
+    pub go_time: Duration,
+}
+
+/// Watches the matches of a [`Broadcast`], read only.
+pub struct Spectator {
+    conn: Option<Connection>,
+    delay: Duration,
+    /// The messages held back for the delay with the arrival time
+    held: VecDeque<(Duration, Message)>,
+    replay: Replay,
+    error: Option<String>,
+}
+
+impl Spectator {
+    /// Show everything `delay` later than it arrives
+    pub fn connect(addr: SocketAddr, delay: Duration) -> anyhow::Result<Self> {
+        let mut conn = Connection::new(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?)?;
+        conn.send(&Message::Watch { version: PROTOCOL_VERSION })?;
+        Ok(Self {
+            conn: Some(conn),
+            delay,
+            held: VecDeque::new(),
+            replay: Default::default(),
+            error: None,
+        })
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn disconnect(&mut self, e: anyhow::Error) {
+        log::warn!("Stopped watching for {:?}", e);
+        self.conn = None;
+        self.error = Some(e.to_string());
+    }
+
+    /// The host sent something bad, stop showing the match too
+    fn refuse(&mut self, e: anyhow::Error) {
+        self.held.clear();
+        self.replay = Default::default();
+        self.disconnect(e);
+    }
+
+    /// Receive the log, returns the new match when its snapshot is due
+    ///
+    /// The bad messages from the host disconnect instead of being shown.
+    pub fn poll(&mut self, now: Duration) -> Option<WatchedMatch> {
+        match self.conn.as_mut().map(|x| x.flush().and_then(|_| x.recv())) {
+            Some(Ok(msgs)) => self.held.extend(msgs.into_iter().map(|msg| (now, msg))),
+            Some(Err(e)) => self.disconnect(e),
+            None => {}
+        }
+        let mut ret = None;
+        while self.held.front().map(|(arrival, _)| *arrival + self.delay <= now).unwrap_or(false) {
+            let (arrival, msg) = self.held.pop_front().unwrap();
+            match msg {
+                Message::Snapshot { info, go_in, sim } => {
+                    let sim = TugSim::from(sim);
+                    if let Err(e) = Replay::check(&info, &sim) {
+                        self.refuse(e);
+                        return None;
+                    }
+                    self.replay = Replay::new(&info, &sim);
+                    ret = Some(WatchedMatch { info, sim, go_time: arrival + self.delay + go_in });
+                }
+                Message::Events { until, clicks } => {
+                    if let Err(e) = self.replay.extend(until, clicks) {
+                        self.refuse(e);
+                        return None;
+                    }
+                }
+                Message::Leave => self.disconnect(anyhow!("The host stopped broadcasting")),
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    /// Step the sim to the log arrived
+    pub fn advance(&mut self, sim: &mut TugSim) {
+        self.replay.advance(sim);
+    }
+}
@@ -0,0 +1,292 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use egui::{Event, Key, Modifiers, PointerButton, Pos2, TouchDeviceId, TouchId, Vec2};
+use serde::{Deserialize, Serialize};
+use winit::event::{MouseButton, TouchPhase, VirtualKeyCode};
+
+use crate::engine::{Clock, InputEvent, InputEventKind, ManualClock, MonotonicClock};
+
+/// Bump it when the recording format changes
+pub const RECORDING_VERSION: u32 = 1;
+
+/// The events baked into one logic frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// The clock time the frame ran at
+    pub time: Duration,
+    pub events: Vec<InputEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    pub version: u32,
+    /// The inputs size scale when recorded, touches are in physical pixels
+    pub size_scale: [f32; 2],
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    pub fn new(size_scale: [f32; 2]) -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            size_scale,
+            frames: vec![],
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)?;
+        let recording: InputRecording = serde_json::from_slice(&data)?;
+        if recording.version != RECORDING_VERSION {
+            return Err(anyhow!("Unsupported recording version {}, expected {}", recording.version, RECORDING_VERSION));
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Get all events in time order
+    pub fn events(&self) -> impl Iterator<Item=&InputEvent> {
+        self.frames.iter().flat_map(|f| f.events.iter())
+    }
+}
+
+/// Record the frames the application runs and save them when it exits.
+pub struct InputRecorder {
+    pub recording: InputRecording,
+    path: Option<PathBuf>,
+}
+
+impl InputRecorder {
+    pub fn new(size_scale: [f32; 2]) -> Self {
+        Self {
+            recording: InputRecording::new(size_scale),
+            path: None,
+        }
+    }
+
+    /// Save to the path when the application exits
+    pub fn save_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Record the frame if it has any event, the replay runs the frames between by the clock
+    pub fn record_frame(&mut self, time: Duration, events: &[InputEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        self.recording.frames.push(RecordedFrame {
+            time,
+            events: events.to_vec(),
+        });
+    }
+
+    pub fn finish(&self) {
+        if let Some(path) = &self.path {
+            match self.recording.save(path) {
+                Ok(_) => log::info!("Saved input recording to {:?}", path),
+                Err(e) => log::warn!("Save input recording to {:?} failed for {:?}", path, e),
+            }
+        }
+    }
+}
+
+/// Feed the recorded frames instead of the window events, one frame a loop.
+///
+/// The application clock is the replay clock, it moves with the real time and stops at the time of every recorded frame.
+pub struct InputReplay {
+    recording: InputRecording,
+    frame: usize,
+    clock: ManualClock,
+    real: MonotonicClock,
+}
+
+impl InputReplay {
+    pub fn new(recording: InputRecording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+            clock: ManualClock::default(),
+            real: MonotonicClock::default(),
+        }
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+
+    /// The clock to give the application
+    pub fn clock(&self) -> ManualClock {
+        self.clock.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+
+    /// Move the clock to the real time but not past the next frame, take the frame when it is due.
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        let real = self.real.now();
+        match self.recording.frames.get(self.frame) {
+            Some(frame) if frame.time <= real => {
+                self.frame += 1;
+                self.clock.set(frame.time);
+                Some(frame.clone())
+            }
+            Some(frame) => {
+                self.clock.set(real.min(frame.time));
+                None
+            }
+            None => {
+                self.clock.set(real);
+                None
+            }
+        }
+    }
+}
+
+/// Turn the input events into the egui events the way egui-winit does, the first touch moves the pointer.
+#[derive(Default)]
+pub struct EguiEvents {
+    /// In points
+    cursor: Pos2,
+    /// The touch moving the pointer
+    pointer_touch: Option<u64>,
+    pub events: Vec<Event>,
+}
+
+impl EguiEvents {
+    /// Positions of the event are in physical pixels
+    // `is_none_or` is newer than the toolchains we build on
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn push(&mut self, kind: InputEventKind, pixels_per_point: f32) {
+        match kind {
+            InputEventKind::Key { key, pressed, repeat, .. } => {
+                if let Some(key) = translate_key(key) {
+                    self.events.push(Event::Key {
+                        key,
+                        pressed,
+                        repeat,
+                        modifiers: Modifiers::NONE,
+                    });
+                }
+            }
+            InputEventKind::Mouse { button, pressed } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    MouseButton::Other(_) => return,
+                };
+                self.events.push(Event::PointerButton {
+                    pos: self.cursor,
+                    button,
+                    pressed,
+                    modifiers: Modifiers::NONE,
+                });
+            }
+            InputEventKind::Touch { id, phase, x, y } => {
+                let pos = Pos2::new(x as f32 / pixels_per_point, y as f32 / pixels_per_point);
+                self.events.push(Event::Touch {
+                    device_id: TouchDeviceId(0),
+                    id: TouchId(id),
+                    phase: match phase {
+                        TouchPhase::Started => egui::TouchPhase::Start,
+                        TouchPhase::Moved => egui::TouchPhase::Move,
+                        TouchPhase::Ended => egui::TouchPhase::End,
+                        TouchPhase::Cancelled => egui::TouchPhase::Cancel,
+                    },
+                    pos,
+                    force: 0.0,
+                });
+                if self.pointer_touch.map_or(true, |x| x == id) {
+                    self.cursor = pos;
+                    match phase {
+                        TouchPhase::Started => {
+                            self.pointer_touch = Some(id);
+                            self.events.push(Event::PointerMoved(pos));
+                            self.events.push(Event::PointerButton { pos, button: PointerButton::Primary, pressed: true, modifiers: Modifiers::NONE });
+                        }
+                        TouchPhase::Moved => self.events.push(Event::PointerMoved(pos)),
+                        TouchPhase::Ended => {
+                            self.pointer_touch = None;
+                            self.events.push(Event::PointerButton { pos, button: PointerButton::Primary, pressed: false, modifiers: Modifiers::NONE });
+                            self.events.push(Event::PointerGone);
+                        }
+                        TouchPhase::Cancelled => {
+                            self.pointer_touch = None;
+                            self.events.push(Event::PointerGone);
+                        }
+                    }
+                }
+            }
+            InputEventKind::CursorMoved { x, y } => {
+                self.cursor = Pos2::new(x as f32 / pixels_per_point, y as f32 / pixels_per_point);
+                self.events.push(Event::PointerMoved(self.cursor));
+            }
+            InputEventKind::CursorLeft => self.events.push(Event::PointerGone),
+            InputEventKind::Wheel { x, y } => self.events.push(Event::Scroll(Vec2::new(x, y) * 50.0)),
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+fn translate_key(key: VirtualKeyCode) -> Option<Key> {
+    Some(match key {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Key::Num0,
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Key::Num1,
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Key::Num2,
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Key::Num3,
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Key::Num4,
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Key::Num5,
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Key::Num6,
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Key::Num7,
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Key::Num8,
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Key::Num9,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::B => Key::B,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::D => Key::D,
+        VirtualKeyCode::E => Key::E,
+        VirtualKeyCode::F => Key::F,
+        VirtualKeyCode::G => Key::G,
+        VirtualKeyCode::H => Key::H,
+        VirtualKeyCode::I => Key::I,
+        VirtualKeyCode::J => Key::J,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::L => Key::L,
+        VirtualKeyCode::M => Key::M,
+        VirtualKeyCode::N => Key::N,
+        VirtualKeyCode::O => Key::O,
+        VirtualKeyCode::P => Key::P,
+        VirtualKeyCode::Q => Key::Q,
+        VirtualKeyCode::R => Key::R,
+        VirtualKeyCode::S => Key::S,
+        VirtualKeyCode::T => Key::T,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
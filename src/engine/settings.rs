@@ -0,0 +1,340 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use winit::event::VirtualKeyCode;
+
+use crate::engine::{Action, ActionBindings, Binding, BotDifficulty, TouchRegion, TugModel};
+
+/// Bump it and add a migration when the schema changes
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Do not write the file more often than this
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `MIGRATIONS[i]` migrates the table of version `i` to version `i + 1`
+const MIGRATIONS: [fn(&mut toml::Table); SETTINGS_VERSION as usize] = [
+    // version 0 files have no version field and the same keys
+    |_| {},
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameSettings {
+    pub version: u32,
+    pub win_target: f32,
+    pub left_color: [f32; 3],
+    pub right_color: [f32; 3],
+    pub bgm_volume: f32,
+    /// The bpm the click streak should keep
+    pub target_bpm: f32,
+    /// The quarter note bpm of the stream trainer metronome
+    pub trainer_bpm: f32,
+    /// The milliseconds from starting the metronome to hearing it, the trainer scores the taps against the heard beats
+    pub metronome_latency_ms: u64,
+    /// Ignore the presses within this milliseconds since the last settled change of the input, 0 to disable
+    pub debounce_ms: u64,
+    pub bindings: ActionBindings,
+    /// The players of the team game
+    pub players: Vec<PlayerSettings>,
+    /// The bot playing the right side of the classic game, none for a human
+    pub bot: Option<BotDifficulty>,
+    pub physics: TugModel,
+    /// Rounds of the tug of war series, 1 for a single round
+    pub best_of: u32,
+    /// The sides swap every round of the series
+    pub swap_sides: bool,
+    /// The name other players see on the network
+    pub player_name: String,
+    /// The local clicks of the network game apply this later so the peer gets them in time, less delay more rollbacks
+    pub input_delay_ms: u64,
+    /// The `host:port` of the leaderboard server the runs are posted to, empty to keep them local
+    pub leaderboard_address: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// One player pulling for a side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSettings {
+    pub name: String,
+    pub color: [f32; 3],
+    pub side: Side,
+    pub bindings: Vec<Binding>,
+}
+
+impl PlayerSettings {
+    fn new(name: &str, color: [f32; 3], side: Side, bindings: Vec<Binding>) -> Self {
+        Self {
+            name: name.into(),
+            color,
+            side,
+            bindings,
+        }
+    }
+}
+
+/// The touch region of the quarter screen, `col` and `row` are 0 or 1
+fn quarter(col: f32, row: f32) -> Binding {
+    Binding::Touch(TouchRegion { min: [col * 800.0, row * 450.0], max: [col * 800.0 + 800.0, row * 450.0 + 450.0] })
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            win_target: 100.0,
+            left_color: [212.0 / 255.0, 205.0 / 255.0, 241.0 / 255.0],
+            right_color: [0.75, 0.0, 0.0],
+            bgm_volume: 0.5,
+            target_bpm: 180.0,
+            trainer_bpm: 150.0,
+            metronome_latency_ms: 30,
+            debounce_ms: 0,
+            bindings: Default::default(),
+            players: vec![
+                PlayerSettings::new("P1", [212.0 / 255.0, 205.0 / 255.0, 241.0 / 255.0], Side::Left,
+                                    vec![Binding::Key(VirtualKeyCode::A), quarter(0.0, 0.0)]),
+                PlayerSettings::new("P2", [0.4, 0.6, 1.0], Side::Left,
+                                    vec![Binding::Key(VirtualKeyCode::Z), quarter(0.0, 1.0)]),
+                PlayerSettings::new("P3", [0.75, 0.0, 0.0], Side::Right,
+                                    vec![Binding::Key(VirtualKeyCode::Key6), Binding::Key(VirtualKeyCode::Numpad6), quarter(1.0, 0.0)]),
+                PlayerSettings::new("P4", [1.0, 0.6, 0.2], Side::Right,
+                                    vec![Binding::Key(VirtualKeyCode::M), quarter(1.0, 1.0)]),
+            ],
+            bot: None,
+            physics: Default::default(),
+            best_of: 1,
+            swap_sides: true,
+            player_name: "Player".into(),
+            input_delay_ms: 20,
+            leaderboard_address: String::new(),
+        }
+    }
+}
+
+impl GameSettings {
+    /// Parse the toml and migrate it to the current version
+    pub fn from_toml(data: &str) -> anyhow::Result<Self> {
+        let mut table: toml::Table = toml::from_str(data)?;
+        let version = match table.get("version") {
+            Some(v) => v.as_integer().ok_or(anyhow!("The settings version is not a integer"))? as u32,
+            None => 0,
+        };
+        if version > SETTINGS_VERSION {
+            return Err(anyhow!("The settings version {} is newer than {}", version, SETTINGS_VERSION));
+        }
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            log::info!("Migrating settings from version {}", from);
+            migration(&mut table);
+        }
+        table.insert("version".into(), toml::Value::Integer(SETTINGS_VERSION as i64));
+        Ok(table.try_into()?)
+    }
+
+    /// The two players of the classic game, bound to the left and right hit actions
+    pub fn classic_players(&self) -> Vec<PlayerSettings> {
+        vec![
+            PlayerSettings::new("Left", self.left_color, Side::Left, self.bindings.get(Action::LeftPlayerHit).to_vec()),
+            PlayerSettings::new("Right", self.right_color, Side::Right, self.bindings.get(Action::RightPlayerHit).to_vec()),
+        ]
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
+
+/// The settings with the file they persist to.
+///
+/// Changes are saved at most once per [`SAVE_INTERVAL`], the application tells the states by
+/// [`crate::engine::StateEvent::SettingsChanged`].
+pub struct Settings {
+    data: GameSettings,
+    path: Option<PathBuf>,
+    changed: bool,
+    dirty: bool,
+    last_save: Option<Duration>,
+}
+
+impl Settings {
+    /// The settings never saved
+    pub fn in_memory() -> Self {
+        Self {
+            data: Default::default(),
+            path: None,
+            changed: false,
+            dirty: false,
+            last_save: None,
+        }
+    }
+
+    /// Load from the file, use the defaults if it does not exist or is broken.
+    ///
+    /// The broken or newer file is moved to `.bak` so the defaults do not overwrite it,
+    /// the settings are not saved if it cannot be moved or read.
+    pub fn load(path: PathBuf) -> Self {
+        let data = match std::fs::read_to_string(&path) {
+            Ok(s) => match GameSettings::from_toml(&s) {
+                Ok(data) => data,
+                Err(e) => {
+                    let mut backup = path.clone().into_os_string();
+                    backup.push(".bak");
+                    log::warn!("Load settings {:?} failed for {:?}, moving it to {:?} and using the defaults", path, e, backup);
+                    if let Err(e) = std::fs::rename(&path, &backup) {
+                        log::warn!("Move settings to {:?} failed for {:?}, they will not be saved", backup, e);
+                        return Self::in_memory();
+                    }
+                    Default::default()
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                log::info!("No settings at {:?}, using the defaults", path);
+                Default::default()
+            }
+            Err(e) => {
+                log::warn!("Read settings {:?} failed for {:?}, they will not be saved", path, e);
+                return Self::in_memory();
+            }
+        };
+        Self {
+            data,
+            path: Some(path),
+            changed: true,
+            dirty: false,
+            last_save: None,
+        }
+    }
+
+    /// Load from the per-user config directory
+    pub fn load_default() -> Self {
+        match config_dir() {
+            Some(dir) => Self::load(dir.join("settings.toml")),
+            None => {
+                log::warn!("Cannot find the config dir, settings will not be saved");
+                Self::in_memory()
+            }
+        }
+    }
+
+    pub fn get(&self) -> &GameSettings {
+        &self.data
+    }
+
+    /// Change the settings, it is marked changed only if the data is different after.
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut GameSettings) -> R) -> R {
+        let old = self.data.clone();
+        let ret = f(&mut self.data);
+        if old != self.data {
+            self.changed = true;
+            self.dirty = true;
+        }
+        ret
+    }
+
+    /// Is it changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    /// Save if changed and not saved recently, `now` is the clock time.
+    pub fn save_if_dirty(&mut self, now: Duration) {
+        if !self.dirty {
+            return;
+        }
+        if let Some(last) = self.last_save {
+            if now.saturating_sub(last) < SAVE_INTERVAL {
+                return;
+            }
+        }
+        self.last_save = Some(now);
+        self.save();
+    }
+
+    pub fn save(&mut self) {
+        self.dirty = false;
+        if let Some(path) = &self.path {
+            let result = self.data.to_toml().and_then(|data| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Ok(std::fs::write(path, data)?)
+            });
+            if let Err(e) = result {
+                log::warn!("Save settings to {:?} failed for {:?}", path, e);
+            }
+        }
+    }
+
+    /// Save if there is anything not saved yet
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.save();
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+pub fn config_dir() -> Option<PathBuf> {
+    Some(ndk_glue::native_activity().internal_data_path().to_path_buf())
+}
+
+#[cfg(not(target_os = "android"))]
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|x| x.join("andy_clicker"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_the_version_0() {
+        let settings = GameSettings::from_toml("win_target = 50.0\nplayer_name = \"Andy\"\n").unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.win_target, 50.0);
+        assert_eq!(settings.player_name, "Andy");
+    }
+
+    #[test]
+    fn refuses_the_newer_version() {
+        let data = format!("version = {}\n", SETTINGS_VERSION + 1);
+        assert!(GameSettings::from_toml(&data).is_err());
+        assert!(GameSettings::from_toml("version = \"one\"\n").is_err());
+    }
+
+    #[test]
+    fn fills_the_missing_keys() {
+        let data = format!("version = {}\ntarget_bpm = 200.0\n", SETTINGS_VERSION);
+        let settings = GameSettings::from_toml(&data).unwrap();
+        assert_eq!(settings.target_bpm, 200.0);
+        assert_eq!(settings, GameSettings { target_bpm: 200.0, ..Default::default() });
+        // the saved settings load the same
+        assert_eq!(GameSettings::from_toml(&settings.to_toml().unwrap()).unwrap(), settings);
+    }
+
+    #[test]
+    fn keeps_the_broken_file() {
+        let dir = std::env::temp_dir().join(format!("andy-clicker-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        let newer = format!("version = {}\n", SETTINGS_VERSION + 1);
+        std::fs::write(&path, &newer).unwrap();
+
+        let mut settings = Settings::load(path.clone());
+        assert_eq!(settings.get(), &GameSettings::default());
+        assert_eq!(std::fs::read_to_string(dir.join("settings.toml.bak")).unwrap(), newer);
+        settings.update(|x| x.bgm_volume = 0.0);
+        settings.flush();
+        assert_eq!(Settings::load(path).get().bgm_volume, 0.0);
+        assert_eq!(std::fs::read_to_string(dir.join("settings.toml.bak")).unwrap(), newer);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
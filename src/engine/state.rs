@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::time::Duration;
 
 use mlua::UserData;
@@ -20,6 +21,8 @@ pub enum Trans {
 pub enum StateEvent<'a> {
     FoundGPU,
     PostUiRender,
+    /// The settings changed in the last frame, the bindings are applied already
+    SettingsChanged,
     Window(&'a WindowEvent<'a>),
 }
 
@@ -34,12 +37,46 @@ pub struct StateData<'a> {
     pub dt: f32,
 }
 
+impl StateData<'_> {
+    /// Get the current time from the window clock
+    #[inline]
+    pub fn now(&self) -> Duration {
+        self.window.clock.now()
+    }
+
+    /// Get the interpolation alpha between the last fixed tick and the next one
+    #[inline]
+    pub fn alpha(&self) -> f32 {
+        self.window.timestep.alpha()
+    }
+}
+
+
+/// Let the boxed states downcast to the concrete type.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 
-pub trait GameState: 'static {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait GameState: AsAny + 'static {
     fn start(&mut self, _: &mut StateData) {}
 
     fn update(&mut self, _: &mut StateData) -> (Trans, LoopState) { (Trans::None, LoopState::WAIT) }
 
+    /// Called at the fixed rate of [`crate::engine::FixedTimestep`] before `update`, `dt` is the tick length.
+    fn fixed_update(&mut self, _: &mut StateData) {}
+
     fn shadow_update(&mut self) -> LoopState { LoopState::WAIT_ALL }
 
     fn render(&mut self, _: &mut StateData, _: &egui::Context) -> Trans { Trans::None }
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+/// Accumulate the clock time and split it into fixed ticks.
+pub struct FixedTimestep {
+    step: Duration,
+    /// Drop the time we cannot catch up with more than this ticks at once
+    pub max_ticks: u32,
+    accumulator: Duration,
+    last: Option<Duration>,
+}
+
+impl FixedTimestep {
+    pub fn new(hz: u32) -> Self {
+        Self {
+            step: Duration::from_secs(1) / hz,
+            max_ticks: (hz / 4).max(1),
+            accumulator: Duration::ZERO,
+            last: None,
+        }
+    }
+
+    /// The seconds for one tick
+    #[inline]
+    pub fn dt(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// How far we are between the last tick and the next one, in `[0, 1)`
+    #[inline]
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+
+    /// Feed the current time and get how many ticks should run now.
+    pub fn advance(&mut self, now: Duration) -> u32 {
+        let last = self.last.replace(now).unwrap_or(now);
+        self.accumulator += now.saturating_sub(last);
+        let mut ticks = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            ticks += 1;
+        }
+        if ticks > self.max_ticks {
+            log::trace!("Fixed timestep skipped {} ticks", ticks - self.max_ticks);
+            ticks = self.max_ticks;
+        }
+        ticks
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(240)
+    }
+}
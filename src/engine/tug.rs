@@ -0,0 +1,597 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Side;
+
+/// The force one click adds for [`Impulse`]
+const IMPULSE: f32 = 1.0;
+/// The velocity keeps `exp(-FRICTION * dt)` every tick for [`Friction`]
+const FRICTION: f32 = 0.8;
+/// The max force for [`Capped`]
+const MAX_FORCE: f32 = 40.0;
+/// The losing side clicks `1 + HANDICAP * |progress| / win_target` times as strong for [`CatchUp`]
+const HANDICAP: f32 = 1.0;
+
+/// How the clicks move the tug of war progress.
+///
+/// The progress is positive to right, the left side pulls it to positive.
+/// The models must give the same progress for the same clicks and ticks.
+pub trait TugPhysics: Send + Sync {
+    /// A player of the side clicked with the strength `1 / interval` in seconds, 0 for the first click.
+    ///
+    /// Returns the force the click added for the player contribution.
+    fn click(&mut self, side: Side, strength: f32) -> f32;
+
+    /// Advance a fixed tick of `dt` seconds
+    fn step(&mut self, dt: f32);
+
+    fn progress(&self) -> f32;
+
+    /// The force pulling the progress now, for the hud
+    fn force(&self) -> f32;
+
+    fn boxed_clone(&self) -> Box<dyn TugPhysics>;
+
+    /// The state for [`TugSnapshot`]
+    fn save(&self) -> Vec<f32>;
+
+    /// Restore the state from [`TugPhysics::save`] of the same model
+    fn load(&mut self, state: &[f32]);
+}
+
+impl Clone for Box<dyn TugPhysics> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+fn sign(side: Side) -> f32 {
+    match side {
+        Side::Left => 1.0,
+        Side::Right => -1.0,
+    }
+}
+
+/// The clicks add to the force and the force integrates into the progress without decay.
+#[derive(Debug, Default, Clone)]
+pub struct Accumulate {
+    a: f32,
+    progress: f32,
+}
+
+impl TugPhysics for Accumulate {
+    fn click(&mut self, side: Side, strength: f32) -> f32 {
+        self.a += sign(side) * strength;
+        strength
+    }
+
+    fn step(&mut self, dt: f32) {
+        self.progress += dt * self.a;
+    }
+
+    fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    fn force(&self) -> f32 {
+        self.a
+    }
+
+    fn boxed_clone(&self) -> Box<dyn TugPhysics> {
+        Box::new(self.clone())
+    }
+
+    fn save(&self) -> Vec<f32> {
+        vec![self.a, self.progress]
+    }
+
+    fn load(&mut self, state: &[f32]) {
+        if let [a, progress] = *state {
+            self.a = a;
+            self.progress = progress;
+        }
+    }
+}
+
+/// Every click moves the progress by the same step, only the count matters.
+#[derive(Debug, Default, Clone)]
+pub struct Impulse {
+    progress: f32,
+    /// The net clicks since the last tick
+    pending: f32,
+}
+
+impl TugPhysics for Impulse {
+    fn click(&mut self, side: Side, _strength: f32) -> f32 {
+        self.pending += sign(side) * IMPULSE;
+        IMPULSE
+    }
+
+    fn step(&mut self, _dt: f32) {
+        self.progress += self.pending;
+        self.pending = 0.0;
+    }
+
+    fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    fn force(&self) -> f32 {
+        self.pending
+    }
+
+    fn boxed_clone(&self) -> Box<dyn TugPhysics> {
+        Box::new(self.clone())
+    }
+
+    fn save(&self) -> Vec<f32> {
+        vec![self.progress, self.pending]
+    }
+
+    fn load(&mut self, state: &[f32]) {
+        if let [progress, pending] = *state {
+            self.progress = progress;
+            self.pending = pending;
+        }
+    }
+}
+
+/// The clicks kick the velocity and the friction slows it down, stop clicking and the bar stops.
+#[derive(Debug, Default, Clone)]
+pub struct Friction {
+    velocity: f32,
+    progress: f32,
+}
+
+impl TugPhysics for Friction {
+    fn click(&mut self, side: Side, strength: f32) -> f32 {
+        self.velocity += sign(side) * strength;
+        strength
+    }
+
+    fn step(&mut self, dt: f32) {
+        self.velocity *= (-FRICTION * dt).exp();
+        self.progress += dt * self.velocity;
+    }
+
+    fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    fn force(&self) -> f32 {
+        self.velocity
+    }
+
+    fn boxed_clone(&self) -> Box<dyn TugPhysics> {
+        Box::new(self.clone())
+    }
+
+    fn save(&self) -> Vec<f32> {
+        vec![self.velocity, self.progress]
+    }
+
+    fn load(&mut self, state: &[f32]) {
+        if let [velocity, progress] = *state {
+            self.velocity = velocity;
+            self.progress = progress;
+        }
+    }
+}
+
+/// Same as [`Accumulate`] but the force stays within [`MAX_FORCE`], the clicks over it are wasted.
+#[derive(Debug, Default, Clone)]
+pub struct Capped {
+    inner: Accumulate,
+}
+
+impl TugPhysics for Capped {
+    fn click(&mut self, side: Side, strength: f32) -> f32 {
+        let before = self.inner.a;
+        self.inner.a = (before + sign(side) * strength).clamp(-MAX_FORCE, MAX_FORCE);
+        (self.inner.a - before).abs()
+    }
+
+    fn step(&mut self, dt: f32) {
+        self.inner.step(dt);
+    }
+
+    fn progress(&self) -> f32 {
+        self.inner.progress
+    }
+
+    fn force(&self) -> f32 {
+        self.inner.a
+    }
+
+    fn boxed_clone(&self) -> Box<dyn TugPhysics> {
+        Box::new(self.clone())
+    }
+
+    fn save(&self) -> Vec<f32> {
+        self.inner.save()
+    }
+
+    fn load(&mut self, state: &[f32]) {
+        self.inner.load(state);
+    }
+}
+
+/// Same as [`Accumulate`] but the losing side clicks stronger the more it is behind.
+#[derive(Debug, Clone)]
+pub struct CatchUp {
+    inner: Accumulate,
+    win_target: f32,
+}
+
+impl CatchUp {
+    pub fn new(win_target: f32) -> Self {
+        Self {
+            inner: Default::default(),
+            win_target,
+        }
+    }
+}
+
+impl TugPhysics for CatchUp {
+    fn click(&mut self, side: Side, strength: f32) -> f32 {
+        let behind = (-sign(side) * self.inner.progress).max(0.0);
+        let scale = 1.0 + HANDICAP * (behind / self.win_target).min(1.0);
+        self.inner.click(side, strength * scale)
+    }
+
+    fn step(&mut self, dt: f32) {
+        self.inner.step(dt);
+    }
+
+    fn progress(&self) -> f32 {
+        self.inner.progress
+    }
+
+    fn force(&self) -> f32 {
+        self.inner.a
+    }
+
+    fn boxed_clone(&self) -> Box<dyn TugPhysics> {
+        Box::new(self.clone())
+    }
+
+    fn save(&self) -> Vec<f32> {
+        self.inner.save()
+    }
+
+    fn load(&mut self, state: &[f32]) {
+        self.inner.load(state);
+    }
+}
+
+/// The physics to pick in the settings
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TugModel {
+    #[default]
+    Accumulate,
+    Impulse,
+    Friction,
+    Capped,
+    CatchUp,
+}
+
+impl TugModel {
+    pub const ALL: [TugModel; 5] = [TugModel::Accumulate, TugModel::Impulse, TugModel::Friction, TugModel::Capped, TugModel::CatchUp];
+
+    pub fn physics(&self, win_target: f32) -> Box<dyn TugPhysics> {
+        match self {
+            TugModel::Accumulate => Box::<Accumulate>::default(),
+            TugModel::Impulse => Box::<Impulse>::default(),
+            TugModel::Friction => Box::<Friction>::default(),
+            TugModel::Capped => Box::<Capped>::default(),
+            TugModel::CatchUp => Box::new(CatchUp::new(win_target)),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TugModel::Accumulate => "Every click adds 1 / interval to the force, the force never decays",
+            TugModel::Impulse => "Every click moves the bar by the same step",
+            TugModel::Friction => "The clicks kick the bar and the friction slows it down",
+            TugModel::Capped => "Same as accumulate but the force is capped",
+            TugModel::CatchUp => "The side behind clicks stronger",
+        }
+    }
+}
+
+/// The clicks of one player in [`TugSim`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimPlayer {
+    pub side: Side,
+    last_click: Option<Duration>,
+    pub clicks: Vec<Duration>,
+    /// How many synthetic key presses are dropped, they are not in the clicks
+    pub synthetic: usize,
+    /// The force the player added to the side in total
+    pub force: f32,
+}
+
+impl SimPlayer {
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            last_click: None,
+            clicks: vec![],
+            synthetic: 0,
+            force: 0.0,
+        }
+    }
+
+    /// Click for now and get the strength for the physics
+    /// the strength is calculated by 1.0 / dur_s
+    fn click(&mut self, now: Duration) -> f32 {
+        self.clicks.push(now);
+        if let Some(last) = &mut self.last_click {
+            let dur = now.saturating_sub(*last);
+            *last = now;
+            if dur.is_zero() {
+                return 0.0;
+            }
+            1.0 / dur.as_secs_f32()
+        } else {
+            self.last_click.replace(now);
+            0.0
+        }
+    }
+}
+
+/// Everything the tug of war simulates, apart from the input and rendering, so it can be snapshotted.
+#[derive(Clone)]
+pub struct TugSim {
+    model: TugModel,
+    physics: Box<dyn TugPhysics>,
+    pub players: Vec<SimPlayer>,
+    win_target: f32,
+    /// The progress before the last step, for interpolation
+    last_progress: f32,
+    /// How many steps are done
+    ticks: u64,
+}
+
+/// The [`TugSim`] to send over the network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TugSnapshot {
+    model: TugModel,
+    physics: Vec<f32>,
+    players: Vec<SimPlayer>,
+    win_target: f32,
+    last_progress: f32,
+    ticks: u64,
+}
+
+impl From<TugSnapshot> for TugSim {
+    fn from(snapshot: TugSnapshot) -> Self {
+        let mut physics = snapshot.model.physics(snapshot.win_target);
+        physics.load(&snapshot.physics);
+        Self {
+            model: snapshot.model,
+            physics,
+            players: snapshot.players,
+            win_target: snapshot.win_target,
+            last_progress: snapshot.last_progress,
+            ticks: snapshot.ticks,
+        }
+    }
+}
+
+impl TugSim {
+    pub fn new(model: TugModel, win_target: f32, sides: impl IntoIterator<Item=Side>) -> Self {
+        Self {
+            model,
+            physics: model.physics(win_target),
+            players: sides.into_iter().map(SimPlayer::new).collect(),
+            win_target,
+            last_progress: 0.0,
+            ticks: 0,
+        }
+    }
+
+    pub fn snapshot(&self) -> TugSnapshot {
+        TugSnapshot {
+            model: self.model,
+            physics: self.physics.save(),
+            players: self.players.clone(),
+            win_target: self.win_target,
+            last_progress: self.last_progress,
+            ticks: self.ticks,
+        }
+    }
+
+    /// Hash the state, the same sims on any machine give the same sum
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a
+        let mut sum = 0xcbf29ce484222325u64;
+        let mut add = |x: u64| {
+            for byte in x.to_le_bytes() {
+                sum = (sum ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        add(self.ticks);
+        add(self.last_progress.to_bits() as u64);
+        self.physics.save().into_iter().for_each(|x| add(x.to_bits() as u64));
+        for player in &self.players {
+            add(player.clicks.len() as u64);
+            add(player.clicks.last().map_or(0, |x| x.as_nanos() as u64));
+            add(player.synthetic as u64);
+            add(player.force.to_bits() as u64);
+        }
+        sum
+    }
+
+    /// The player clicked, the clicks after the end do nothing.
+    /// A `synthetic` key press does not pull, it is only counted for the verdict.
+    pub fn click(&mut self, player: usize, time: Duration, synthetic: bool) {
+        if self.is_over() {
+            return;
+        }
+        let player = &mut self.players[player];
+        if synthetic {
+            player.synthetic += 1;
+            return;
+        }
+        let strength = player.click(time);
+        player.force += self.physics.click(player.side, strength);
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        self.last_progress = self.physics.progress();
+        self.physics.step(dt);
+        self.ticks += 1;
+    }
+
+    /// How many steps are done, the clicks happen between the steps
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Positive to right
+    pub fn progress(&self) -> f32 {
+        self.physics.progress()
+    }
+
+    /// The progress between the last step and now
+    pub fn interpolated(&self, alpha: f32) -> f32 {
+        self.last_progress + (self.progress() - self.last_progress) * alpha
+    }
+
+    pub fn force(&self) -> f32 {
+        self.physics.force()
+    }
+
+    pub fn model(&self) -> TugModel {
+        self.model
+    }
+
+    pub fn win_target(&self) -> f32 {
+        self.win_target
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.progress().abs() >= self.win_target
+    }
+
+    /// The side the progress is going to
+    pub fn winner(&self) -> Side {
+        if self.progress() > 0.0 { Side::Left } else { Side::Right }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 240.0;
+
+    /// Click both sides at uneven rates for a second and give the progress after every tick
+    fn play(physics: &mut dyn TugPhysics) -> Vec<f32> {
+        (0..240).map(|tick| {
+            if tick % 7 == 0 {
+                physics.click(Side::Left, 240.0 / 7.0);
+            }
+            if tick % 11 == 3 {
+                physics.click(Side::Right, 240.0 / 11.0);
+            }
+            physics.step(DT);
+            physics.progress()
+        }).collect()
+    }
+
+    #[test]
+    fn models_are_deterministic() {
+        for model in TugModel::ALL {
+            let first = play(model.physics(50.0).as_mut());
+            let second = play(model.physics(50.0).as_mut());
+            assert_eq!(first, second, "{:?}", model);
+            assert!(first.last().unwrap().abs() > 0.0, "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn models_restore_from_save() {
+        for model in TugModel::ALL {
+            let mut played = model.physics(50.0);
+            play(played.as_mut());
+            let mut restored = model.physics(50.0);
+            restored.load(&played.save());
+            assert_eq!(play(played.as_mut()), play(restored.as_mut()), "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn accumulate_keeps_force() {
+        let mut physics = Accumulate::default();
+        assert_eq!(physics.click(Side::Left, 4.0), 4.0);
+        physics.click(Side::Right, 1.0);
+        for _ in 0..240 {
+            physics.step(DT);
+        }
+        assert_eq!(physics.force(), 3.0);
+        assert!((physics.progress() - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn impulse_counts_clicks() {
+        let mut physics = Impulse::default();
+        physics.click(Side::Left, 100.0);
+        physics.click(Side::Left, 0.0);
+        physics.click(Side::Right, 5.0);
+        physics.step(DT);
+        physics.step(DT);
+        assert_eq!(physics.progress(), IMPULSE);
+        assert_eq!(physics.force(), 0.0);
+    }
+
+    #[test]
+    fn friction_decays() {
+        let mut physics = Friction::default();
+        physics.click(Side::Right, 10.0);
+        let mut last = physics.force().abs();
+        for _ in 0..240 {
+            physics.step(DT);
+            assert!(physics.force().abs() < last);
+            last = physics.force().abs();
+        }
+        assert!((physics.force() + 10.0 * (-FRICTION).exp()).abs() < 1e-3);
+        // the bar slows down and never passes the full glide
+        let first = physics.progress();
+        for _ in 0..240 {
+            physics.step(DT);
+        }
+        assert!((physics.progress() - first).abs() < first.abs());
+        assert!(physics.progress() > -10.0 / FRICTION);
+    }
+
+    #[test]
+    fn capped_clamps_force() {
+        let mut physics = Capped::default();
+        assert_eq!(physics.click(Side::Left, 30.0), 30.0);
+        assert_eq!(physics.click(Side::Left, 30.0), MAX_FORCE - 30.0);
+        assert_eq!(physics.click(Side::Left, 30.0), 0.0);
+        assert_eq!(physics.force(), MAX_FORCE);
+        physics.click(Side::Right, 200.0);
+        assert_eq!(physics.force(), -MAX_FORCE);
+    }
+
+    #[test]
+    fn catch_up_scales_behind() {
+        let mut physics = CatchUp::new(10.0);
+        assert_eq!(physics.click(Side::Right, 2.0), 2.0);
+        for _ in 0..240 * 3 {
+            physics.step(DT);
+        }
+        // the right side is 6 of 10 ahead
+        assert!((physics.progress() + 6.0).abs() < 1e-3);
+        let behind = physics.click(Side::Left, 2.0);
+        assert!((behind - 2.0 * 1.6).abs() < 1e-3);
+        // the side ahead gets no bonus
+        assert_eq!(physics.click(Side::Right, 2.0), 2.0);
+        physics.inner.progress = -100.0;
+        assert_eq!(physics.click(Side::Left, 1.0), 1.0 + HANDICAP);
+    }
+}
@@ -2,10 +2,11 @@ use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::WindowBuilder;
 
+use crate::engine::{InputRecorder, InputRecording, InputReplay};
 use crate::engine::app::Application;
 
-mod engine;
-mod state;
+pub mod engine;
+pub mod state;
 
 
 pub fn real_main() {
@@ -19,9 +20,33 @@ pub fn real_main() {
         .build(&event_loop)
         .unwrap();
     log::info!("Got the window");
-    let main = Application::new(window, &event_loop);
+    let size = window.inner_size();
+    let mut main = Application::new(window, &event_loop);
     log::info!("Got the main application");
-    main.run_loop(event_loop, state::MainMenu::default());
+    if let Ok(path) = std::env::var("ANDY_RECORD") {
+        log::info!("Recording inputs to {}", path);
+        main = main.with_recorder(InputRecorder::new([size.width as f32 / 1600.0, size.height as f32 / 900.0]).save_to(path));
+    }
+    if let Ok(path) = std::env::var("ANDY_REPLAY") {
+        match InputRecording::load(&path) {
+            Ok(recording) => {
+                log::info!("Replaying inputs from {}", path);
+                main = main.with_replay(InputReplay::new(recording));
+            }
+            Err(e) => log::warn!("Load input recording {} failed for {:?}", path, e),
+        }
+    }
+    // two processes can play over localhost without the menus
+    if let Ok(port) = std::env::var("ANDY_HOST") {
+        let port = port.parse().unwrap_or(engine::DEFAULT_PORT);
+        log::info!("Hosting on port {}", port);
+        main.run_loop(event_loop, state::LobbyState::hosting(port));
+    } else if let Ok(address) = std::env::var("ANDY_JOIN") {
+        log::info!("Joining {}", address);
+        main.run_loop(event_loop, state::LobbyState::joining(address));
+    } else {
+        main.run_loop(event_loop, state::MainMenu::default());
+    }
 }
 
 
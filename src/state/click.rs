@@ -1,18 +1,57 @@
-use std::time::SystemTime;
+use std::time::Duration;
 
-use egui::{Button, Context, Frame, Pos2, Rect, Vec2};
-use winit::event::VirtualKeyCode;
+use egui::{Button, CollapsingHeader, ComboBox, Context, DragValue, Frame, Pos2, Rect, Vec2};
+use crate::engine::{Action, analyze_clicks, ClickStats, FREE_MODE, GameState, InputEventKind, Leaderboard, LoopState, RunSubmission, SessionHistory, SessionRecord, StateData, Trans, Verdict};
+use super::countdown::{COUNTDOWN, countdown_label};
+use super::results::{cps_chart, interval_histogram, stats_panel, verdict_label};
 
-use crate::engine::{GameState, LoopState, StateData, Trans};
+/// The window to check the endurance cps floor
+const ENDURANCE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ClickMode {
+    /// Measured from the first click until reset
+    #[default]
+    Free,
+    /// Click as many as possible in the seconds
+    Timed { secs: u64 },
+    /// Click the count as fast as possible
+    Count { clicks: usize },
+    /// Keep clicking until the cps in the last second drops below the floor
+    Endurance { min_cps: u32 },
+}
+
+impl ClickMode {
+    pub const PRESETS: [ClickMode; 9] = [
+        ClickMode::Free,
+        ClickMode::Timed { secs: 5 },
+        ClickMode::Timed { secs: 10 },
+        ClickMode::Timed { secs: 30 },
+        ClickMode::Timed { secs: 60 },
+        ClickMode::Count { clicks: 50 },
+        ClickMode::Count { clicks: 100 },
+        ClickMode::Endurance { min_cps: 5 },
+        ClickMode::Endurance { min_cps: 8 },
+    ];
+
+    /// The name shown and used to rank in the history
+    pub fn name(&self) -> String {
+        match self {
+            ClickMode::Free => FREE_MODE.into(),
+            ClickMode::Timed { secs } => format!("Timed {}s", secs),
+            ClickMode::Count { clicks } => format!("Count {}", clicks),
+            ClickMode::Endurance { min_cps } => format!("Endurance {} CPS", min_cps),
+        }
+    }
+}
 
 struct ClickData {
     max_cps: f64,
-    clicks: Vec<SystemTime>,
+    clicks: Vec<Duration>,
 }
 
 impl ClickData {
-    fn click_first() -> ClickData {
-        let now = SystemTime::now();
+    fn click_first(now: Duration) -> ClickData {
         ClickData {
             max_cps: 0.0,
             clicks: vec![now],
@@ -20,71 +59,341 @@ impl ClickData {
     }
 }
 
+/// The finished run of the modes except free
+struct ClickResult {
+    clicks: usize,
+    duration: Duration,
+    max_cps: f64,
+}
+
+/// The rect in egui points to the 1600x900 design coordinates
+fn design_rect(rect: Rect, pixels_per_point: f32, size_scale: [f32; 2]) -> Rect {
+    let scale = Vec2::new(pixels_per_point / size_scale[0], pixels_per_point / size_scale[1]);
+    Rect::from_min_max((rect.min.to_vec2() * scale).to_pos2(), (rect.max.to_vec2() * scale).to_pos2())
+}
+
 #[derive(Default)]
 pub struct ClickState {
+    mode: ClickMode,
     click: Option<ClickData>,
+    /// When the countdown started, the modes except free start at the go
+    countdown: Option<Duration>,
+    result: Option<ClickResult>,
+    /// The stats of the clicks, computed again when the clicks or the target bpm change
+    stats: Option<ClickStats>,
+    verdict: Option<Verdict>,
+    /// How many synthetic presses of the hits were dropped in the run, only counted for the verdict
+    synthetic: usize,
+    /// Our widgets in the last render in the design coordinates, presses on them are handled by egui
+    widgets: Vec<Rect>,
+    /// Is the mode popup open in the last render
+    popup_open: bool,
+    /// The finished run not posted to the leaderboard yet
+    submission: Option<RunSubmission>,
 }
 
 impl ClickState {
-    fn click(&mut self) {
-        let now = SystemTime::now();
+    pub fn new(mode: ClickMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    fn go_time(&self) -> Option<Duration> {
+        self.countdown.map(|x| x + COUNTDOWN)
+    }
+
+    /// Clear the run and start the countdown again
+    fn restart(&mut self, now: Duration) {
+        self.click = None;
+        self.result = None;
+        self.stats = None;
+        self.verdict = None;
+        self.synthetic = 0;
+        self.countdown = if self.mode == ClickMode::Free { None } else { Some(now) };
+    }
+
+    fn click(&mut self, now: Duration, history: &mut SessionHistory) {
+        // the run may end before this click
+        self.check_end(now, history);
+        if self.result.is_some() {
+            return;
+        }
+        if let Some(go) = self.go_time() {
+            if now <= go {
+                return;
+            }
+        }
         if let Some(click) = &mut self.click {
             click.clicks.push(now);
         } else {
-            self.click = Some(ClickData::click_first());
+            self.click = Some(ClickData::click_first(now));
+        }
+        if let (ClickMode::Count { clicks }, Some(click)) = (self.mode, &self.click) {
+            if click.clicks.len() >= clicks {
+                self.end(now, history);
+            }
+        }
+    }
+
+    /// Count the synthetic press while the run goes, it is not a click
+    fn synthetic_press(&mut self, now: Duration, history: &mut SessionHistory) {
+        self.check_end(now, history);
+        let going = match self.go_time() {
+            Some(go) => now > go,
+            None => true,
+        };
+        if self.result.is_none() && going {
+            self.synthetic += 1;
+            self.verdict = None;
+        }
+    }
+
+    /// Check the time based end of the modes
+    fn check_end(&mut self, now: Duration, history: &mut SessionHistory) {
+        let go = if let (None, Some(go)) = (&self.result, self.go_time()) { go } else { return; };
+        match self.mode {
+            ClickMode::Timed { secs } => {
+                let end = go + Duration::from_secs(secs);
+                if now >= end {
+                    self.end(end, history);
+                }
+            }
+            ClickMode::Endurance { min_cps } => {
+                if now < go + ENDURANCE_WINDOW {
+                    return;
+                }
+                let from = now - ENDURANCE_WINDOW;
+                let recent = self.click.as_ref().map_or(0, |x| x.clicks.iter().rev().take_while(|t| **t > from).count());
+                if (recent as f64) < min_cps as f64 * ENDURANCE_WINDOW.as_secs_f64() {
+                    self.end(now, history);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the clicks, the seconds and the cps of the run until now
+    fn cps(&self, now: Duration) -> Option<(usize, f64, f64)> {
+        let click = self.click.as_ref()?;
+        let all = click.clicks.len();
+        let start = self.go_time().unwrap_or(click.clicks[0]);
+        let sec = now.saturating_sub(start).as_secs_f64();
+        Some((all, sec, all as f64 / sec))
+    }
+
+    fn update_max_cps(&mut self, now: Duration) {
+        if let Some((_, sec, cps)) = self.cps(now) {
+            let click = self.click.as_mut().unwrap();
+            if sec >= 1.0 {
+                click.max_cps = cps.max(click.max_cps);
+            }
+        }
+    }
+
+    /// End the run of the modes except free and save it
+    fn end(&mut self, end: Duration, history: &mut SessionHistory) {
+        let go = self.go_time().unwrap();
+        self.update_max_cps(end);
+        let (clicks, max_cps) = self.click.as_ref().map_or((0, 0.0), |x| (x.clicks.len(), x.max_cps));
+        let duration = end.saturating_sub(go);
+        let clicks_at = self.click.as_ref().map_or(&[][..], |x| &x.clicks[..]);
+        if let Some(mut record) = SessionRecord::from_clicks(self.mode.name(), clicks_at, max_cps, self.synthetic) {
+            record.duration = duration;
+            history.push(record);
+        }
+        self.submission = RunSubmission::from_clicks("", self.mode.name(), clicks_at, duration, self.synthetic);
+        self.result = Some(ClickResult {
+            clicks,
+            duration,
+            max_cps,
+        });
+    }
+
+    fn refresh_stats(&mut self, target_bpm: f64) {
+        let clicks = self.click.as_ref().map_or(&[][..], |x| &x.clicks[..]);
+        if clicks.len() < 2 {
+            self.stats = None;
+            self.verdict = None;
+        } else if self.verdict.is_none() || !matches!(&self.stats, Some(x) if x.clicks == clicks.len() && x.target_bpm == target_bpm) {
+            self.stats = Some(ClickStats::from_clicks(clicks, target_bpm));
+            self.verdict = Some(analyze_clicks(clicks, self.synthetic));
+        }
+    }
+
+    /// End the free session and save it to the history
+    fn finish(&mut self, history: &mut SessionHistory) {
+        if self.mode != ClickMode::Free {
+            return;
+        }
+        let synthetic = std::mem::take(&mut self.synthetic);
+        if let Some(click) = self.click.take() {
+            if let Some(record) = SessionRecord::from_clicks(FREE_MODE, &click.clicks, click.max_cps, synthetic) {
+                self.submission = RunSubmission::from_clicks("", FREE_MODE, &click.clicks, record.duration, synthetic);
+                history.push(record);
+            }
+        }
+    }
+
+    /// Post the finished run as the player
+    fn submit(&mut self, leaderboard: &Leaderboard, player: &str) {
+        if let Some(mut run) = self.submission.take() {
+            run.player = player.into();
+            leaderboard.submit(run);
         }
     }
 }
 
 impl GameState for ClickState {
+    fn start(&mut self, s: &mut StateData) {
+        self.restart(s.now());
+    }
+
     fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
-        (if s.window.inputs.cur_frame_input.pressing.contains(&VirtualKeyCode::Escape) { Trans::Pop } else { Trans::None }, LoopState::POLL)
+        let window = &mut *s.window;
+        let events: Vec<_> = window.inputs.drain_events().collect();
+        let size_scale = window.inputs.size_scale;
+        for event in events {
+            let pos = match event.kind {
+                InputEventKind::Mouse { .. } => window.inputs.cursor_design_position(),
+                InputEventKind::Touch { x, y, .. } => Some([x as f32 / size_scale[0], y as f32 / size_scale[1]]),
+                _ => None,
+            };
+            if pos.is_some_and(|[x, y]| self.popup_open || self.widgets.iter().any(|r| r.contains(Pos2::new(x, y)))) {
+                continue;
+            }
+            let hits = [Action::LeftPlayerHit, Action::RightPlayerHit];
+            let clicked = match event.kind {
+                InputEventKind::Mouse { pressed: true, .. } => true,
+                _ => hits.iter().any(|x| window.inputs.action_pressed_by(*x, &event)),
+            };
+            if clicked {
+                self.click(event.time, &mut window.history);
+            } else if hits.iter().any(|x| window.inputs.action_synthetic_by(*x, &event)) {
+                self.synthetic_press(event.time, &mut window.history);
+            }
+        }
+        let now = window.clock.now();
+        self.check_end(now, &mut window.history);
+        if self.result.is_none() {
+            self.update_max_cps(now);
+        }
+        self.refresh_stats(window.settings.get().target_bpm as f64);
+        self.submit(&window.leaderboard, &window.settings.get().player_name);
+        (if window.inputs.action_held(Action::Back) { Trans::Pop } else { Trans::None }, LoopState::POLL)
+    }
+
+    fn stop(&mut self, s: &mut StateData) {
+        self.finish(&mut s.window.history);
+        self.submit(&s.window.leaderboard, &s.window.settings.get().player_name);
     }
 
     fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
         egui::CentralPanel::default()
             .frame(Frame::none())
             .show(ctx, |ui| {
-                let now = SystemTime::now();
+                let now = s.now();
                 ui.allocate_ui_at_rect(Rect {
                     min: Pos2::new(0.0, 0.0),
                     max: ui.max_rect().max,
                 }, |ui| {
+                    let mut widgets = vec![];
+                    let mut mode = self.mode;
+                    let combo = ComboBox::from_label("Mode")
+                        .selected_text(mode.name())
+                        .show_ui(ui, |ui| {
+                            for preset in ClickMode::PRESETS {
+                                ui.selectable_value(&mut mode, preset, preset.name());
+                            }
+                        });
+                    widgets.push(combo.response.rect);
+                    if mode != self.mode {
+                        self.finish(&mut s.window.history);
+                        self.mode = mode;
+                        self.restart(now);
+                    }
+
                     let bs = Vec2::new(ui.max_rect().width(), ui.max_rect().height() / 4.0);
-                    ui.add_enabled_ui(self.click.is_some(), |ui| {
-                        if ui.add_sized(bs, Button::new("Reset")).clicked() {
-                            self.click = None;
-                        }
-                    });
-                    ui.horizontal(|ui| {
-                        if ui.add_sized(bs, Button::new("Click")).clicked() {
-                            self.click();
-                        }
-                    });
-
-                    for _ in 0..s.window.inputs.pressed_any_cur_frame {
-                        self.click();
+                    let reset_text = if self.mode == ClickMode::Free { "Reset" } else { "Retry" };
+                    let reset = ui.add_enabled_ui(self.click.is_some() || self.result.is_some(), |ui| {
+                        ui.add_sized(bs, Button::new(reset_text))
+                    }).inner;
+                    if reset.clicked() {
+                        self.finish(&mut s.window.history);
+                        self.restart(now);
+                    }
+                    let click = ui.horizontal(|ui| {
+                        ui.add_sized(bs, Button::new("Click"))
+                    }).inner;
+                    if click.clicked() {
+                        self.click(now, &mut s.window.history);
                     }
 
-                    if let Some(click) = &mut self.click {
-                        let all = click.clicks.len();
-                        let start = click.clicks[0];
-                        let dur = now.duration_since(start).map_err(|x| x.duration()).unwrap_or_else(|x| x);
-                        let sec = dur.as_secs_f64();
-                        let cps = all as f64 / sec;
+                    widgets.push(reset.rect);
+                    widgets.push(click.rect);
+
+                    if let Some(result) = &self.result {
+                        let sec = result.duration.as_secs_f64();
+                        let cps = if sec > 0.0 { result.clicks as f64 / sec } else { 0.0 };
+                        ui.vertical_centered(|ui| {
+                            ui.heading(format!("{} Finished!", self.mode.name()));
+                            ui.label(format!("Clicks: {} Time: {:.3}s", result.clicks, sec));
+                            ui.label(format!("CPS: {:.2} BPM: {:.2}", cps, 15.0 * cps));
+                            ui.label(format!("MAX: CPS: {:.2} BPM: {:.2}", result.max_cps, 15.0 * result.max_cps));
+                        });
+                    } else if let (Some((all, sec, cps)), Some(click)) = (self.cps(now), &self.click) {
                         let bpm = 15.0 * cps;
-                        if sec >= 1.0 {
-                            click.max_cps = cps.max(click.max_cps);
-                        }
                         let max_bpm = 15.0 * click.max_cps;
                         ui.vertical_centered(|ui| {
+                            match self.mode {
+                                ClickMode::Timed { secs } => { ui.label(format!("Time left: {:.2}s", (secs as f64 - sec).max(0.0))); }
+                                ClickMode::Count { clicks } => { ui.label(format!("Clicks left: {} Time: {:.2}s", clicks.saturating_sub(all), sec)); }
+                                ClickMode::Endurance { min_cps } => { ui.label(format!("Keep above {} CPS! Time: {:.2}s", min_cps, sec)); }
+                                ClickMode::Free => {}
+                            }
                             ui.label(format!("Clicks: {}", all));
                             ui.label(format!("CPS: {:.2} BPM: {:.2}", cps, bpm));
                             ui.label(format!("MAX: CPS: {:.2} BPM: {:.2}", click.max_cps, max_bpm));
                         });
                     }
+                    if let Some(click) = &self.click {
+                        let start = self.go_time().unwrap_or(click.clicks[0]);
+                        let end = self.result.as_ref().map_or(now, |x| start + x.duration);
+                        let height = ui.max_rect().height() / 5.0;
+                        let target_bpm = s.window.settings.get().target_bpm as f64;
+                        ui.columns(2, |columns| {
+                            cps_chart(&mut columns[0], "click-cps", &click.clicks, start, end, target_bpm, height);
+                            interval_histogram(&mut columns[1], "click-intervals", &click.clicks, height);
+                        });
+                    }
+                    if let Some(stats) = &self.stats {
+                        let header = CollapsingHeader::new("Stats").default_open(true).show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Target BPM:");
+                                s.window.settings.update(|x| ui.add(DragValue::new(&mut x.target_bpm).clamp_range(60.0..=600.0)));
+                            });
+                            stats_panel(ui, "click-stats", stats);
+                            if let Some(verdict) = &self.verdict {
+                                verdict_label(ui, verdict);
+                            }
+                        });
+                        widgets.push(header.header_response.rect);
+                        widgets.extend(header.body_response.map(|x| x.rect));
+                    }
+                    if let Some(countdown) = self.countdown {
+                        ui.vertical_centered(|ui| {
+                            countdown_label(ui, now.saturating_sub(countdown).as_secs_f64());
+                        });
+                    }
+                    let pixels_per_point = ctx.pixels_per_point();
+                    let size_scale = s.window.inputs.size_scale;
+                    self.widgets = widgets.into_iter().map(|x| design_rect(x, pixels_per_point, size_scale)).collect();
                 });
             });
+        self.popup_open = ctx.memory(|x| x.any_popup_open());
+        // the mode change and the reset finish the free run
+        self.submit(&s.window.leaderboard, &s.window.settings.get().player_name);
         Trans::None
     }
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+use egui::{Context, DragValue, Frame, Grid};
+
+use crate::engine::{Action, Binding, GameState, LoopState, RegionCapture, StateData, Trans};
+
+/// The screen to rebind the actions.
+#[derive(Default)]
+pub struct ControlsState {
+    /// Waiting a key, mouse button or dragged touch region for the action
+    listening: Option<Action>,
+    capture: RegionCapture,
+}
+
+pub(crate) fn binding_text(binding: &Binding) -> String {
+    match binding {
+        Binding::Key(key) => format!("{:?}", key),
+        Binding::Mouse(button) => format!("Mouse {:?}", button),
+        Binding::Touch(region) => format!("Touch ({:.0}, {:.0})-({:.0}, {:.0})", region.min[0], region.min[1], region.max[0], region.max[1]),
+    }
+}
+
+impl ControlsState {
+    /// Open the controls waiting a binding for the action
+    pub fn listening(action: Action) -> Self {
+        Self {
+            listening: Some(action),
+            ..Default::default()
+        }
+    }
+}
+
+impl GameState for ControlsState {
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        if let Some(action) = self.listening {
+            let inputs = &s.window.inputs;
+            if let Some(binding) = inputs.pressed_binding().or_else(|| self.capture.update(inputs).map(Binding::Touch)) {
+                s.window.settings.update(|x| x.bindings.bind(action, binding));
+                self.listening = None;
+            }
+            (Trans::None, LoopState::POLL)
+        } else if s.window.inputs.action_pressed(Action::Back) {
+            (Trans::Pop, LoopState::POLL)
+        } else {
+            (Trans::None, LoopState::POLL)
+        }
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Controls");
+                    Grid::new("controls").striped(true).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(format!("{:?}", action));
+                            let mut removed = None;
+                            for binding in s.window.settings.get().bindings.get(action) {
+                                if ui.button(binding_text(binding)).on_hover_text("Click to remove").clicked() {
+                                    removed = Some(*binding);
+                                }
+                            }
+                            if let Some(binding) = removed {
+                                s.window.settings.update(|x| x.bindings.unbind(action, &binding));
+                            }
+                            if self.listening == Some(action) {
+                                ui.label("Press a key or drag a region...");
+                            } else if ui.button("+").clicked() {
+                                self.listening = Some(action);
+                                self.capture.clear();
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Debounce (ms):");
+                        s.window.settings.update(|x| ui.add(DragValue::new(&mut x.debounce_ms).clamp_range(0..=50)))
+                            .on_hover_text("Ignore the presses this soon after the last press or release of the same input");
+                    });
+                    if ui.button("Reset").clicked() {
+                        s.window.settings.update(|x| x.bindings = Default::default());
+                        self.listening = None;
+                    }
+                    if ui.button("Back").clicked() {
+                        ret = Trans::Pop;
+                    }
+                });
+            });
+        ret
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use egui::{Color32, Label, RichText, Ui};
+
+/// The time from the countdown start to go
+pub(crate) const COUNTDOWN: Duration = Duration::from_secs(3);
+
+/// Show "Ready/Get/Set" until go and "Go" fading out in the next half second.
+///
+/// `sec` is the seconds since the countdown started.
+pub(crate) fn countdown_label(ui: &mut Ui, sec: f64) {
+    let go = COUNTDOWN.as_secs_f64();
+    if sec > go + 1.0 {
+        return;
+    }
+    let mut r = 255;
+    let text = if sec <= go - 2.0 {
+        format!("Ready {:.02}", go - sec)
+    } else if sec <= go - 1.0 {
+        format!("Get   {:.02}", go - sec)
+    } else if sec <= go {
+        format!("Set   {:.02}", go - sec)
+    } else {
+        r = if sec >= go + 0.5 {
+            (((go + 1.0 - sec) / 0.5) * 255.0) as u8
+        } else {
+            255
+        };
+        "Go".into()
+    };
+    ui.add(Label::new(RichText::new(text).color(Color32::from_rgba_premultiplied(255, 255, 255, r)).heading()));
+}
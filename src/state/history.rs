@@ -0,0 +1,131 @@
+use egui::{ComboBox, Context, Frame, Grid, ScrollArea};
+use egui::plot::{Legend, Line, Plot, PlotPoints};
+
+use crate::engine::{Action, GameState, LoopState, RankKey, SessionRecord, StateData, Trans, unix_now};
+use super::results::verdict_label;
+
+/// How many sessions the leaderboard shows
+const LEADERBOARD_SIZE: usize = 10;
+
+/// The name, the key to rank and the text of a personal best
+type BestRow = (&'static str, fn(&SessionRecord) -> f64, fn(&SessionRecord) -> String);
+
+/// The screen shows the personal bests, trends and leaderboards of the saved sessions.
+#[derive(Default)]
+pub struct HistoryState {
+    mode: Option<String>,
+}
+
+pub(super) fn format_age(timestamp: u64) -> String {
+    let secs = unix_now().saturating_sub(timestamp);
+    match secs {
+        0..=59 => "just now".into(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} d ago", secs / 86400),
+    }
+}
+
+fn best_by<'a>(sessions: &[&'a SessionRecord], f: impl Fn(&SessionRecord) -> f64) -> Option<&'a SessionRecord> {
+    sessions.iter().copied().max_by(|a, b| f(a).total_cmp(&f(b)))
+}
+
+impl GameState for HistoryState {
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        if s.window.inputs.action_pressed(Action::Back) {
+            (Trans::Pop, LoopState::POLL)
+        } else {
+            (Trans::None, LoopState::POLL)
+        }
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        let history = &s.window.history;
+        let modes = history.modes();
+        if !matches!(&self.mode, Some(x) if modes.contains(x.as_str())) {
+            self.mode = modes.iter().next().map(|x| x.to_string());
+        }
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("History");
+                    ComboBox::from_label("Mode")
+                        .selected_text(self.mode.as_deref().unwrap_or("-"))
+                        .show_ui(ui, |ui| {
+                            for mode in &modes {
+                                ui.selectable_value(&mut self.mode, Some(mode.to_string()), *mode);
+                            }
+                        });
+                    if ui.button("Back").clicked() {
+                        ret = Trans::Pop;
+                    }
+                });
+                let mode = if let Some(mode) = &self.mode { mode } else {
+                    ui.label("No sessions yet, finish a click test first.");
+                    return;
+                };
+                let sessions: Vec<_> = history.by_mode(mode).collect();
+
+                ui.separator();
+                ui.heading("Personal Bests");
+                Grid::new("bests").show(ui, |ui| {
+                    // the count modes are best done quickly
+                    let shortest = RankKey::of_mode(mode) == RankKey::MinDuration;
+                    let bests: [BestRow; 4] = [
+                        ("Max CPS", |x| x.max_cps, |x| format!("{:.2}", x.max_cps)),
+                        ("Average CPS", SessionRecord::avg_cps, |x| format!("{:.2}", x.avg_cps())),
+                        ("Clicks", |x| x.clicks as f64, |x| x.clicks.to_string()),
+                        ("Duration", if shortest { |x| -x.duration.as_secs_f64() } else { |x| x.duration.as_secs_f64() }, |x| format!("{:.2}s", x.duration.as_secs_f64())),
+                    ];
+                    for (name, key, text) in bests {
+                        if let Some(best) = best_by(&sessions, key) {
+                            ui.label(name);
+                            ui.label(text(best));
+                            ui.label(format_age(best.timestamp));
+                            ui.end_row();
+                        }
+                    }
+                });
+
+                ui.separator();
+                ui.heading("Trend");
+                let max: PlotPoints = sessions.iter().enumerate().map(|(i, x)| [i as f64 + 1.0, x.max_cps]).collect();
+                let avg: PlotPoints = sessions.iter().enumerate().map(|(i, x)| [i as f64 + 1.0, x.avg_cps()]).collect();
+                Plot::new("trend")
+                    .height(ui.available_height() / 3.0)
+                    .include_y(0.0)
+                    .legend(Legend::default())
+                    .show(ui, |plot| {
+                        plot.line(Line::new(max).name("Max CPS"));
+                        plot.line(Line::new(avg).name("Average CPS"));
+                    });
+
+                ui.separator();
+                ui.heading("Leaderboard");
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("leaderboard").striped(true).show(ui, |ui| {
+                        for title in ["#", "Max CPS", "Average CPS", "Clicks", "Duration", "When", "Verdict"] {
+                            ui.strong(title);
+                        }
+                        ui.end_row();
+                        for (i, x) in history.leaderboard(mode, LEADERBOARD_SIZE).into_iter().enumerate() {
+                            ui.label((i + 1).to_string());
+                            ui.label(format!("{:.2}", x.max_cps));
+                            ui.label(format!("{:.2}", x.avg_cps()));
+                            ui.label(x.clicks.to_string());
+                            ui.label(format!("{:.2}s", x.duration.as_secs_f64()));
+                            ui.label(format_age(x.timestamp));
+                            match &x.verdict {
+                                Some(verdict) => verdict_label(ui, verdict),
+                                None => { ui.label("-"); }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        ret
+    }
+}
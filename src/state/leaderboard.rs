@@ -0,0 +1,142 @@
+use egui::{Color32, ComboBox, Context, Frame, Grid, ScrollArea, TextEdit};
+
+use crate::engine::{Action, GameState, Leaderboard, LeaderboardEntry, LoopState, Pending, StateData, Trans};
+use super::history::format_age;
+
+/// How many runs the table shows
+const TOP_COUNT: usize = 20;
+
+/// The screen browses the top runs of the boards on the leaderboard server.
+#[derive(Default)]
+pub struct LeaderboardState {
+    boards: Vec<String>,
+    board: Option<String>,
+    entries: Vec<LeaderboardEntry>,
+    pending_boards: Option<Pending<Vec<String>>>,
+    pending_top: Option<Pending<Vec<LeaderboardEntry>>>,
+    error: Option<String>,
+}
+
+impl LeaderboardState {
+    fn refresh(&mut self, leaderboard: &Leaderboard) {
+        self.error = None;
+        self.pending_boards = Some(leaderboard.boards());
+        if let Some(board) = &self.board {
+            self.pending_top = Some(leaderboard.top(board, TOP_COUNT));
+        }
+    }
+}
+
+impl GameState for LeaderboardState {
+    fn start(&mut self, s: &mut StateData) {
+        if s.window.leaderboard.is_enabled() {
+            self.refresh(&s.window.leaderboard);
+        }
+    }
+
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        if let Some(result) = self.pending_boards.as_ref().and_then(|x| x.try_take()) {
+            self.pending_boards = None;
+            match result {
+                Ok(boards) => {
+                    if !matches!(&self.board, Some(x) if boards.contains(x)) {
+                        self.board = boards.first().cloned();
+                        self.entries.clear();
+                        if let Some(board) = &self.board {
+                            self.pending_top = Some(s.window.leaderboard.top(board, TOP_COUNT));
+                        }
+                    }
+                    self.boards = boards;
+                }
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+        if let Some(result) = self.pending_top.as_ref().and_then(|x| x.try_take()) {
+            self.pending_top = None;
+            match result {
+                Ok(entries) => self.entries = entries,
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+        if s.window.inputs.action_pressed(Action::Back) {
+            (Trans::Pop, LoopState::POLL)
+        } else {
+            (Trans::None, LoopState::POLL)
+        }
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Leaderboard");
+                    let mut board = self.board.clone();
+                    ComboBox::from_label("Board")
+                        .selected_text(board.as_deref().unwrap_or("-"))
+                        .show_ui(ui, |ui| {
+                            for x in &self.boards {
+                                ui.selectable_value(&mut board, Some(x.clone()), x);
+                            }
+                        });
+                    if board != self.board {
+                        self.board = board;
+                        self.entries.clear();
+                        self.refresh(&s.window.leaderboard);
+                    }
+                    if ui.button("Back").clicked() {
+                        ret = Trans::Pop;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Server:");
+                    s.window.settings.update(|x| ui.add(TextEdit::singleline(&mut x.leaderboard_address).hint_text("host:port").desired_width(160.0)))
+                        .on_hover_text("The finished runs are posted here, empty to keep them local");
+                    if ui.button("Refresh").clicked() {
+                        s.window.leaderboard = Leaderboard::http(&s.window.settings.get().leaderboard_address);
+                        if s.window.leaderboard.is_enabled() {
+                            self.refresh(&s.window.leaderboard);
+                        } else {
+                            self.boards.clear();
+                            self.board = None;
+                            self.entries.clear();
+                        }
+                    }
+                    if self.pending_boards.is_some() || self.pending_top.is_some() {
+                        ui.spinner();
+                    }
+                });
+                if let Some(e) = &self.error {
+                    ui.colored_label(Color32::LIGHT_RED, e);
+                }
+                ui.separator();
+                if !s.window.leaderboard.is_enabled() {
+                    ui.label("Set the leaderboard server to post the runs and see the tables.");
+                    return;
+                }
+                if self.board.is_none() {
+                    ui.label("No ranked runs yet.");
+                    return;
+                }
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("leaderboard").striped(true).show(ui, |ui| {
+                        for header in ["Rank", "Player", "CPS", "Clicks", "Time", "When"] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+                        for entry in &self.entries {
+                            ui.label(format!("#{}", entry.rank));
+                            ui.label(&entry.player);
+                            ui.label(format!("{:.2}", entry.cps));
+                            ui.label(entry.clicks.to_string());
+                            ui.label(format!("{:.2}s", entry.duration.as_secs_f64()));
+                            ui.label(format_age(entry.timestamp));
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        ret
+    }
+}
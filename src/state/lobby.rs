@@ -0,0 +1,307 @@
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use egui::{Color32, Context, DragValue, Frame, Grid, TextEdit};
+use egui::color_picker;
+
+use crate::engine::{Action, Beacon, DEFAULT_PORT, Discovery, GameSettings, GameState, LoopState, Message, NetRole, NetSession, PeerInfo, PlayerSettings, Rollback, Side, StateData, Trans, TugModel};
+use super::MulClickState;
+use super::menu::side_color_picker;
+
+/// The host starts the match this later so the start message arrives before the countdown,
+/// both players count down from the same moment
+const START_DELAY: Duration = Duration::from_millis(500);
+/// Listen for the hosts again this often while it fails
+const LISTEN_RETRY: Duration = Duration::from_secs(1);
+
+/// The screen to find, host or join the tug of war on the local network and get ready.
+pub struct LobbyState {
+    port: u16,
+    address: String,
+    session: Option<NetSession>,
+    error: Option<String>,
+    /// Host or join when the state starts, from the environment
+    auto: Option<NetRole>,
+    /// Hears the hosts while not in a session
+    discovery: Option<Discovery>,
+    /// Try to listen for the hosts again at the time
+    next_listen: Duration,
+    /// Tells the clients while hosting
+    beacon: Option<Beacon>,
+    ready: bool,
+}
+
+impl Default for LobbyState {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            address: format!("127.0.0.1:{}", DEFAULT_PORT),
+            session: None,
+            error: None,
+            auto: None,
+            discovery: None,
+            next_listen: Duration::ZERO,
+            beacon: None,
+            ready: false,
+        }
+    }
+}
+
+impl LobbyState {
+    /// Host on the port when the state starts
+    pub fn hosting(port: u16) -> Self {
+        Self {
+            port,
+            auto: Some(NetRole::Host),
+            ..Default::default()
+        }
+    }
+
+    /// Join the address when the state starts
+    pub fn joining(address: String) -> Self {
+        Self {
+            address,
+            auto: Some(NetRole::Client),
+            ..Default::default()
+        }
+    }
+
+    /// The host plays the left side
+    fn side(role: NetRole) -> Side {
+        match role {
+            NetRole::Host => Side::Left,
+            NetRole::Client => Side::Right,
+        }
+    }
+
+    fn local(settings: &GameSettings, role: NetRole) -> PeerInfo {
+        PeerInfo {
+            name: settings.player_name.clone(),
+            color: match Self::side(role) {
+                Side::Left => settings.left_color,
+                Side::Right => settings.right_color,
+            },
+        }
+    }
+
+    fn open(&mut self, settings: &GameSettings, role: NetRole) {
+        let local = Self::local(settings, role);
+        let session = match role {
+            NetRole::Host => NetSession::host(self.port, local),
+            NetRole::Client => self.address.to_socket_addrs()
+                .map_err(anyhow::Error::from)
+                .and_then(|mut x| x.next().ok_or(anyhow!("No address for {}", self.address)))
+                .and_then(|addr| NetSession::connect(addr, local)),
+        };
+        match session {
+            Ok(session) => {
+                if role == NetRole::Host {
+                    let port = session.local_addr().map(|x| x.port()).unwrap_or(self.port);
+                    self.beacon = Beacon::new(settings.player_name.clone(), port)
+                        .map_err(|e| log::warn!("Create the beacon failed for {:?}", e))
+                        .ok();
+                }
+                self.session = Some(session);
+                self.discovery = None;
+                self.ready = false;
+                self.error = None;
+            }
+            Err(e) => {
+                log::warn!("Open the {:?} session failed for {:?}", role, e);
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// The local player clicks with both hit actions, the start time is in the host clock
+    fn start_match(&mut self, settings: &GameSettings, start_time: Duration, win_target: f32, physics: TugModel, input_delay: u64) -> Trans {
+        let session = self.session.take().unwrap();
+        self.beacon = None;
+        self.ready = false;
+        let mut bindings = settings.bindings.get(Action::LeftPlayerHit).to_vec();
+        bindings.extend_from_slice(settings.bindings.get(Action::RightPlayerHit));
+        let (local, peer) = (session.local().clone(), session.peer().cloned().unwrap());
+        let (left, right, left_bindings, right_bindings) = match session.role() {
+            NetRole::Host => (local, peer, bindings, vec![]),
+            NetRole::Client => (peer, local, vec![], bindings),
+        };
+        let players = vec![
+            PlayerSettings { name: left.name, color: left.color, side: Side::Left, bindings: left_bindings },
+            PlayerSettings { name: right.name, color: right.color, side: Side::Right, bindings: right_bindings },
+        ];
+        Trans::Push(Box::new(MulClickState::new(win_target, physics, players).with_net(session, start_time, input_delay)))
+    }
+}
+
+impl GameState for LobbyState {
+    fn start(&mut self, s: &mut StateData) {
+        if let Some(role) = self.auto.take() {
+            self.open(s.window.settings.get(), role);
+        }
+    }
+
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        let now = s.now();
+        if let Some(session) = &mut self.session {
+            if session.peer().is_some() && s.window.inputs.action_pressed(Action::Confirm) {
+                self.ready = !self.ready;
+            }
+            let local = Self::local(s.window.settings.get(), session.role());
+            session.set_local(local, self.ready);
+            session.poll(now);
+            if let (Some(beacon), None) = (&mut self.beacon, session.peer()) {
+                beacon.poll(now);
+            }
+            if session.role() == NetRole::Host && session.all_ready() {
+                let settings = s.window.settings.get();
+                let start_time = now + START_DELAY;
+                let input_delay = Rollback::delay_ticks(settings.input_delay_ms);
+                session.send(&Message::Start { start_time, win_target: settings.win_target, physics: settings.physics, input_delay });
+                return (self.start_match(settings, start_time, settings.win_target, settings.physics, input_delay), LoopState::POLL);
+            }
+            for msg in session.take_messages() {
+                if let Message::Start { start_time, win_target, physics, input_delay } = msg {
+                    return (self.start_match(s.window.settings.get(), start_time, win_target, physics, input_delay), LoopState::POLL);
+                }
+            }
+        } else {
+            if self.discovery.is_none() && now >= self.next_listen {
+                self.next_listen = now + LISTEN_RETRY;
+                self.discovery = Discovery::new()
+                    .map_err(|e| log::warn!("Listen for the hosts failed for {:?}", e))
+                    .ok();
+            }
+            if let Some(discovery) = &mut self.discovery {
+                discovery.poll(now);
+            }
+        }
+        if s.window.inputs.action_pressed(Action::Back) {
+            if let Some(session) = &mut self.session {
+                session.leave();
+            }
+            return (Trans::Pop, LoopState::POLL);
+        }
+        (Trans::None, LoopState::POLL)
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("LAN Game");
+                    let mut leave = false;
+                    let mut join = None;
+                    if let Some(session) = &mut self.session {
+                        match (session.role(), session.local_addr()) {
+                            (NetRole::Host, Some(addr)) => ui.label(format!("Hosting on port {}", addr.port())),
+                            (NetRole::Host, None) => ui.label("Hosting"),
+                            (NetRole::Client, _) => ui.label(format!("Joined {}", self.address)),
+                        };
+                        Grid::new("lobby").striped(true).show(ui, |ui| {
+                            ui.label("Player");
+                            ui.label("Color");
+                            ui.label("Ready");
+                            ui.end_row();
+                            ui.label(format!("{} (You)", session.local().name));
+                            side_color_picker(ui, &mut s.window.settings, Self::side(session.role()));
+                            ui.checkbox(&mut self.ready, "").on_hover_text("Confirm to toggle");
+                            ui.end_row();
+                            match session.peer() {
+                                Some(peer) => {
+                                    ui.label(&peer.name);
+                                    let [r, g, b] = peer.color.map(|x| (x * 255.0) as u8);
+                                    color_picker::show_color(ui, Color32::from_rgb(r, g, b), ui.spacing().interact_size);
+                                    ui.label(if session.peer_ready() { "Ready" } else { "Not ready" });
+                                }
+                                None => {
+                                    ui.label("Waiting for the other player...");
+                                }
+                            }
+                            ui.end_row();
+                        });
+                        if let Some(rtt) = session.rtt() {
+                            ui.label(format!("Ping {:.1}ms", rtt.as_secs_f64() * 1000.0));
+                        }
+                        if session.role() == NetRole::Host {
+                            ui.horizontal(|ui| {
+                                ui.label("Input delay:");
+                                s.window.settings.update(|x| ui.add(DragValue::new(&mut x.input_delay_ms).clamp_range(0..=200).suffix("ms")))
+                                    .on_hover_text("The clicks apply this later so the other player gets them in time, less delay more rollbacks");
+                            });
+                        }
+                        if let Some(e) = session.error() {
+                            ui.colored_label(Color32::LIGHT_RED, e);
+                        }
+                        if self.ready && session.peer().is_some() && !session.peer_ready() {
+                            ui.label("Waiting for the other player to get ready");
+                        }
+                        leave = ui.button("Leave").clicked();
+                        if leave {
+                            session.leave();
+                        }
+                    } else {
+                        ui.horizontal(|ui| {
+                            ui.label("Name:");
+                            s.window.settings.update(|x| ui.add(TextEdit::singleline(&mut x.player_name).desired_width(120.0)));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Port:");
+                            ui.add(DragValue::new(&mut self.port));
+                            if ui.button("Host").clicked() {
+                                self.open(s.window.settings.get(), NetRole::Host);
+                            }
+                        });
+                        ui.separator();
+                        match &self.discovery {
+                            Some(discovery) if discovery.hosts().is_empty() => {
+                                ui.label("Looking for the games on the network...");
+                            }
+                            Some(discovery) => {
+                                Grid::new("found-hosts").striped(true).show(ui, |ui| {
+                                    for host in discovery.hosts() {
+                                        ui.label(&host.name);
+                                        ui.label(host.addr.to_string());
+                                        if ui.button("Join").clicked() {
+                                            join = Some(host.addr.to_string());
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                            }
+                            None => {
+                                ui.label("Can not look for the games, join by the address");
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Address:");
+                            ui.add(TextEdit::singleline(&mut self.address).desired_width(160.0));
+                            if ui.button("Join").clicked() {
+                                join = Some(self.address.clone());
+                            }
+                        });
+                        if let Some(e) = &self.error {
+                            ui.colored_label(Color32::LIGHT_RED, e);
+                        }
+                    }
+                    if leave {
+                        self.session = None;
+                        self.beacon = None;
+                    }
+                    if let Some(address) = join {
+                        self.address = address;
+                        self.open(s.window.settings.get(), NetRole::Client);
+                    }
+                    if ui.button("Back").clicked() {
+                        if let Some(session) = &mut self.session {
+                            session.leave();
+                        }
+                        ret = Trans::Pop;
+                    }
+                });
+            });
+        ret
+    }
+}
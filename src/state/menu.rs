@@ -3,17 +3,27 @@ use std::f32::consts::PI;
 use std::io::Cursor;
 use std::time::Duration;
 
-use egui::{Button, Context, Frame, Pos2, Rect, Slider, Vec2};
+use egui::{Button, ComboBox, Context, Frame, Pos2, Rect, Response, Slider, Ui, Vec2};
 use kira::{LoopBehavior, Volume};
 use kira::sound::static_sound::{StaticSoundData, StaticSoundHandle, StaticSoundSettings};
 use kira::tween::{Easing, Tween};
 use rand::{Rng, thread_rng};
-use specs::WorldExt;
-use winit::event::VirtualKeyCode;
 
-use crate::engine::{GameState, LoopState, StateData, StateEvent, Trans};
+use crate::engine::{Action, BotDifficulty, GameState, LoopState, Settings, Side, StateData, StateEvent, Trans, TugModel};
 use crate::engine::invert_color::InvertColorRenderer;
 use crate::engine::point::{PointRenderer, PointVertexData};
+use super::series::start_match;
+
+/// Pick the color of the side, the lobby uses it for the network players too
+pub(crate) fn side_color_picker(ui: &mut Ui, settings: &mut Settings, side: Side) -> Response {
+    settings.update(|x| ui.color_edit_button_rgb(match side {
+        Side::Left => &mut x.left_color,
+        Side::Right => &mut x.right_color,
+    }))
+}
+
+/// The spell card shoots at this interval
+const SHOOT_DT: f32 = 1.0 / 60.0;
 
 #[derive(Default)]
 pub struct QuestionSpellCard {
@@ -21,9 +31,40 @@ pub struct QuestionSpellCard {
     delta: VecDeque<(f32, f32)>,
     angle: f32,
     a: f32,
+    shoot_acc: f32,
+    /// the screen size got in the last render
+    size: Option<[f32; 2]>,
 }
 
 impl QuestionSpellCard {
+    fn tick(&mut self, dt: f32) {
+        let [w, h] = if let Some(size) = self.size { size } else { return; };
+        let center = [w / 2.0, h / 2.0];
+        self.shoot_acc += dt;
+        while self.shoot_acc >= SHOOT_DT {
+            self.shoot_acc -= SHOOT_DT;
+            for i in 0..3 {
+                self.create_bullet(self.angle + i as f32 * 120.0, center);
+            }
+            self.a += SHOOT_DT * 9.0;
+            self.a %= 360.0;
+            self.angle += self.a;
+            self.angle %= 360.0;
+        }
+        while let Some(fst) = self.ps.front() {
+            if fst.pos[0] < -100.0 || fst.pos[1] < -100.0 || fst.pos[0] > w + 100.0 || fst.pos[1] > h + 100.0 {
+                self.ps.pop_front();
+                self.delta.pop_front();
+            } else {
+                break;
+            }
+        }
+        for x in self.ps.iter_mut().zip(self.delta.iter()) {
+            x.0.pos[0] += x.1.0 * 300.0 * dt;
+            x.0.pos[1] += x.1.1 * 300.0 * dt;
+        }
+    }
+
     fn create_bullet(&mut self, angle: f32, center: [f32; 2]) {
         let d = (angle * PI / 180.0).sin_cos();
         self.delta.push_back(d);
@@ -38,32 +79,27 @@ impl QuestionSpellCard {
     }
 }
 
+#[derive(Default)]
 pub struct MainMenu {
-    win_target: f32,
     bg: Option<egui::TextureHandle>,
-    left_color: [f32; 3],
-    right_color: [f32; 3],
-    vol: f32,
     handle: Option<StaticSoundHandle>,
     sp: QuestionSpellCard,
 }
 
-impl Default for MainMenu {
-    fn default() -> Self {
-        Self {
-            win_target: 100.0,
-            bg: None,
-            left_color: [212.0 / 255.0, 205.0 / 255.0, 241.0 / 255.0],
-            right_color: [0.75, 0.0, 0.0],
-            vol: 0.5,
-            handle: None,
-            sp: Default::default(),
+impl MainMenu {
+    fn apply_volume(&mut self, vol: f32) {
+        if let Some(h) = &mut self.handle {
+            if let Err(e) = h.set_volume(Volume::Amplitude(vol as _), Tween {
+                start_time: Default::default(),
+                duration: Duration::from_secs(0),
+                easing: Easing::Linear,
+            }) {
+                log::warn!("Set bgm volume failed for {:?}", e);
+            }
         }
     }
 }
 
-impl MainMenu {}
-
 impl GameState for MainMenu {
     fn start(&mut self, s: &mut StateData) {
         if let Some(gpu) = &s.window.gpu {
@@ -72,17 +108,22 @@ impl GameState for MainMenu {
         }
         if let Some(al) = &mut s.window.audio {
             let music_data = include_bytes!("../../sign/th08_18.mp3");
-            let mut s = StaticSoundSettings::default();
-            s.loop_behavior = Some(LoopBehavior { start_position: 0.0 });
+            let mut settings = StaticSoundSettings::default();
+            settings.loop_behavior = Some(LoopBehavior { start_position: 0.0 });
+            settings.volume = Volume::Amplitude(s.window.settings.get().bgm_volume as _);
             let handle = al.manager.play(StaticSoundData::from_cursor(Cursor::new(music_data),
-                                                                      s).expect("Read sound data failed"))
+                                                                      settings).expect("Read sound data failed"))
                 .expect("Play bgm failed");
             self.handle = Some(handle);
         }
     }
 
+    fn fixed_update(&mut self, s: &mut StateData) {
+        self.sp.tick(s.dt);
+    }
+
     fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
-        if s.window.inputs.is_pressed(&[VirtualKeyCode::S]) {
+        if s.window.inputs.action_pressed(Action::StartClickTest) {
             s.window.inputs.pressed_any_cur_frame = 0;
             (Trans::Push(Box::new(super::ClickState::default())), LoopState::POLL)
         } else {
@@ -103,69 +144,108 @@ impl GameState for MainMenu {
                 }, |ui| {
                     ui.horizontal_centered(|ui| {
                         ui.heading("Win Target:");
-                        ui.add(Slider::new(&mut self.win_target, 100.0..=1000.0));
+                        let settings = &mut s.window.settings;
+                        settings.update(|x| ui.add(Slider::new(&mut x.win_target, 100.0..=1000.0)));
+                        ui.heading("Opponent:");
+                        settings.update(|x| {
+                            let text = x.bot.map(|b| format!("{:?} Bot", b)).unwrap_or_else(|| "Human".into());
+                            ComboBox::from_id_source("opponent").selected_text(text).show_ui(ui, |ui| {
+                                ui.selectable_value(&mut x.bot, None, "Human");
+                                for difficulty in BotDifficulty::ALL {
+                                    ui.selectable_value(&mut x.bot, Some(difficulty), format!("{:?} Bot", difficulty));
+                                }
+                            });
+                        });
+                        ui.heading("Best of:");
+                        settings.update(|x| {
+                            ComboBox::from_id_source("best-of").selected_text(x.best_of.to_string()).show_ui(ui, |ui| {
+                                for n in [1, 3, 5, 7] {
+                                    ui.selectable_value(&mut x.best_of, n, n.to_string());
+                                }
+                            });
+                            ui.checkbox(&mut x.swap_sides, "Swap sides");
+                        });
+                        ui.heading("Physics:");
+                        settings.update(|x| {
+                            ComboBox::from_id_source("physics").selected_text(format!("{:?}", x.physics)).show_ui(ui, |ui| {
+                                for model in TugModel::ALL {
+                                    ui.selectable_value(&mut x.physics, model, format!("{:?}", model)).on_hover_text(model.description());
+                                }
+                            });
+                        });
                         let mut started = false;
                         if ui.add_sized(size, Button::new("Start")).clicked() {
                             started = true;
                         }
-                        if let Some(h) = &mut self.handle {
+                        if self.handle.is_some() {
                             ui.heading("BGM Vol:");
-                            if ui.add(Slider::new(&mut self.vol, 0.0..=1.0)).changed() {
-                                println!("Changed");
-                                h.set_volume(Volume::Amplitude(self.vol as _), Tween {
-                                    start_time: Default::default(),
-                                    duration: Duration::from_secs(0),
-                                    easing: Easing::Linear,
-                                }).unwrap();
-                            }
+                            settings.update(|x| ui.add(Slider::new(&mut x.bgm_volume, 0.0..=1.0)));
                         }
-                        if s.window.inputs.is_pressed(&[VirtualKeyCode::Return]) {
+                        if s.window.inputs.action_pressed(Action::Confirm) {
                             started = true;
                         }
 
                         if started {
-                            ret = Trans::Push(Box::new(super::MulClickState::new(self.win_target, ui, self.left_color, self.right_color)))
+                            let settings = s.window.settings.get();
+                            let bot = settings.bot.map(|bot| (Side::Right, format!("{:?} Bot", bot), bot.profile(&s.window.history)));
+                            ret = Trans::Push(start_match(settings, settings.classic_players(), bot))
                         }
                     });
                 });
                 ui.horizontal_centered(|ui| {
+                    let settings = &mut s.window.settings;
+                    let side_text = |action| settings.get().bindings.get(action).iter().map(super::binding_text).collect::<Vec<_>>().join(", ");
+                    let (left_text, right_text) = (side_text(Action::LeftPlayerHit), side_text(Action::RightPlayerHit));
                     ui.heading("Left Color:");
-                    ui.color_edit_button_rgb(&mut self.left_color);
+                    side_color_picker(ui, settings, Side::Left);
+                    if ui.small_button(left_text).on_hover_text("Click to add a binding").clicked() {
+                        ret = Trans::Push(Box::new(super::ControlsState::listening(Action::LeftPlayerHit)));
+                    }
                     ui.add_space(size.x);
                     ui.heading("Right Color:");
-                    ui.color_edit_button_rgb(&mut self.right_color);
+                    side_color_picker(ui, settings, Side::Right);
+                    if ui.small_button(right_text).on_hover_text("Click to add a binding").clicked() {
+                        ret = Trans::Push(Box::new(super::ControlsState::listening(Action::RightPlayerHit)));
+                    }
+                    ui.add_space(size.x);
+                    if ui.button("Controls").clicked() {
+                        ret = Trans::Push(Box::new(super::ControlsState::default()));
+                    }
+                    if ui.button("Team Game").clicked() {
+                        ret = Trans::Push(Box::new(super::TeamSetupState::default()));
+                    }
+                    if ui.button("LAN Game").clicked() {
+                        ret = Trans::Push(Box::new(super::LobbyState::default()));
+                    }
+                    if ui.button("Spectate").clicked() {
+                        ret = Trans::Push(Box::new(super::SpectateState::default()));
+                    }
+                    if ui.button("History").clicked() {
+                        ret = Trans::Push(Box::new(super::HistoryState::default()));
+                    }
+                    if ui.button("Leaderboard").clicked() {
+                        ret = Trans::Push(Box::new(super::LeaderboardState::default()));
+                    }
+                    if ui.button("Stream Trainer").clicked() {
+                        ret = Trans::Push(Box::new(super::StreamTrainerState::default()));
+                    }
                 })
             });
-        let (w, h) = {
-            let cfg = &s.window.gpu.as_ref().unwrap().surface_cfg;
+        let (w, h) = if let Some(gpu) = &s.window.gpu {
+            let cfg = &gpu.surface_cfg;
             (cfg.width as f32, cfg.height as f32)
+        } else {
+            let rect = ctx.screen_rect();
+            (rect.width(), rect.height())
         };
-        // run spellcard
+        // draw spellcard, it runs in fixed update
         let sp = &mut self.sp;
-        let center = [w / 2.0, h / 2.0];
-        for i in 0..3 {
-            sp.create_bullet(sp.angle + i as f32 * 120.0, center);
-        }
-        while let Some(fst) = sp.ps.front() {
-            if fst.pos[0] < -100.0 || fst.pos[1] < -100.0 || fst.pos[0] > w + 100.0 || fst.pos[1] > h + 100.0 {
-                sp.ps.pop_front();
-                sp.delta.pop_front();
-            } else {
-                break;
-            }
-        }
-        for x in sp.ps.iter_mut().zip(sp.delta.iter()) {
-            x.0.pos[0] += x.1.0 * 300.0 * s.dt;
-            x.0.pos[1] += x.1.1 * 300.0 * s.dt;
+        sp.size = Some([w, h]);
+        if let (Some(render), Some(pr)) = (&s.window.render, s.window.world.try_fetch::<PointRenderer>()) {
+            let slice = sp.ps.as_slices();
+            pr.render(&s.window, &render.views.get_screen().view, slice.0);
+            pr.render(&s.window, &render.views.get_screen().view, slice.1);
         }
-        let pr = s.window.world.read_resource::<PointRenderer>();
-        let slice = sp.ps.as_slices();
-        pr.render(&s.window, &s.window.render.as_ref().unwrap().views.get_screen().view, slice.0);
-        pr.render(&s.window, &s.window.render.as_ref().unwrap().views.get_screen().view, slice.1);
-        sp.a += s.dt * 9.0;
-        sp.a %= 360.0;
-        sp.angle += sp.a;
-        sp.angle %= 360.0;
         ret
     }
 
@@ -184,7 +264,10 @@ impl GameState for MainMenu {
     }
 
     fn on_event(&mut self, s: Option<&mut StateData>, e: StateEvent) {
-        if matches!(e, StateEvent::FoundGPU) {
+        if matches!(e, StateEvent::SettingsChanged) {
+            let vol = s.unwrap().window.settings.get().bgm_volume;
+            self.apply_volume(vol);
+        } else if matches!(e, StateEvent::FoundGPU) {
             let s = s.unwrap();
             if !s.window.world.has_value::<InvertColorRenderer>() {
                 if let Some(gpu) = &s.window.gpu {
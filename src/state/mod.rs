@@ -1,7 +1,24 @@
 pub use click::*;
+pub use controls::*;
+pub use history::*;
+pub use leaderboard::*;
+pub use lobby::*;
 pub use menu::*;
 pub use mul_click::*;
+pub use spectate::*;
+pub use team::*;
+pub use trainer::*;
 
 mod click;
+mod controls;
+mod countdown;
+mod history;
+mod leaderboard;
+mod lobby;
 mod menu;
-mod mul_click;
\ No newline at end of file
+mod mul_click;
+mod results;
+mod series;
+mod spectate;
+mod team;
+mod trainer;
\ No newline at end of file
@@ -1,164 +1,368 @@
 use std::default::Default;
-use std::time::SystemTime;
+use std::time::Duration;
 
-use egui::{Color32, Context, Event, Frame, Image, Key, Label, Pos2, Rect, RichText, TextureHandle, TextureOptions, TouchPhase, Ui};
-use winit::event::VirtualKeyCode;
-
-use crate::engine::{GameState, LoopState, StateData, StateEvent, Trans};
+use egui::{Align2, CollapsingHeader, Color32, Context, FontId, Frame, Grid, Label, Pos2, Rect, RichText, Ui, Vec2};
+use crate::engine::{Action, analyze_clicks, Bot, BotProfile, Broadcast, ClickStats, Debouncer, GameState, Leaderboard, LogClick, LoopState, MatchInfo, Message, NetRole, NetSession, PlayerSettings, Rollback, ROLLBACK_HZ, RunSubmission, Side, Spectator, StateData, StateEvent, Trans, TUG_BOARD, TugModel, TugSim, Verdict, WatchedMatch};
 use crate::engine::invert_color::{InvertColorCircle, InvertColorRenderer};
+use super::countdown::{COUNTDOWN, countdown_label};
+use super::results::{stats_panel, verdict_label};
+use super::series::Series;
+
+/// How long the round result shows before the series goes on
+const ROUND_END_DELAY: Duration = Duration::from_millis(2500);
 
-#[derive(Default)]
-struct ClickData {
-    last_click: Option<SystemTime>,
+struct Player {
+    settings: PlayerSettings,
+    /// The bot clicks for the player instead of the bindings
+    bot: Option<BotProfile>,
 }
 
-impl ClickData {
-    /// Click for now and get the value for click
-    /// the value is calculated by 1.0 / dur_s
-    pub(crate) fn click(&mut self, now: SystemTime) -> f32 {
-        if let Some(last) = &mut self.last_click {
-            let dur = now.duration_since(*last).unwrap();
-            *last = now;
-            1.0 / dur.as_secs_f32()
-        } else {
-            self.last_click.replace(now);
-            0.0
-        }
-    }
+/// The match over the network with one local and one remote player, both peers simulate it with rollback.
+struct NetPlay {
+    session: NetSession,
+    rollback: Rollback,
 }
 
 pub struct MulClickState {
-    start_time: Option<SystemTime>,
-    left_click: ClickData,
-    right_click: ClickData,
-    pressing_a: bool,
-    pressing_6: bool,
-    left: TextureHandle,
-    right: TextureHandle,
-    last_time: Option<SystemTime>,
-    end_time: Option<SystemTime>,
-    /// positive to right
-    cur_progress: f32,
-    /// positive to right
-    a: f32,
+    start_time: Option<Duration>,
+    players: Vec<Player>,
+    /// The running bots with the player index, made at start
+    bots: Vec<(usize, Bot)>,
+    last_time: Option<Duration>,
+    end_time: Option<Duration>,
+    model: TugModel,
+    /// The clicks and the progress, the players are in the same order
+    sim: TugSim,
     win_target: f32,
     effects: Vec<InvertColorCircle>,
+    debouncer: Debouncer,
+    /// The stats and verdicts of every player when the game ends
+    stats: Option<Vec<(ClickStats, Verdict)>>,
+    /// The series the round belongs to
+    series: Option<Series>,
+    net: Option<NetPlay>,
+    /// The clicks not published to the broadcast yet
+    log: Vec<LogClick>,
+    /// The broadcast has the log before the tick
+    published: u64,
+    /// Watching the match of another host, read only
+    spectate: Option<Spectator>,
     exit: bool,
 }
 
+fn color32(color: [f32; 3]) -> Color32 {
+    let to = |x: f32| (x * 255.0) as u8;
+    Color32::from_rgb(to(color[0]), to(color[1]), to(color[2]))
+}
+
 impl MulClickState {
-    pub(crate) fn new(win_target: f32, ui: &Ui, left_color: [f32; 3], right_color: [f32; 3]) -> Self {
-        let to = |x| (x * 255.0) as u8;
-        let left = ui.ctx().load_texture("left-color",
-                                         egui::ColorImage::new([1, 1],
-                                                               Color32::from_rgb(
-                                                                   to(left_color[0]), to(left_color[1]), to(left_color[2]))),
-                                         TextureOptions::NEAREST);
-        let right = ui.ctx().load_texture("right-color",
-                                          egui::ColorImage::new([1, 1],
-                                                                Color32::from_rgb(
-                                                                    to(right_color[0]), to(right_color[1]), to(right_color[2]))),
-                                          TextureOptions::NEAREST);
+    /// The players pull the bar for their sides, the force of the side is the sum of its players.
+    pub(crate) fn new(win_target: f32, model: TugModel, players: Vec<PlayerSettings>) -> Self {
+        let players: Vec<Player> = players.into_iter().map(|settings| Player { settings, bot: None }).collect();
         Self {
             start_time: None,
-            left_click: Default::default(),
-            right_click: Default::default(),
-            pressing_a: false,
+            sim: TugSim::new(model, win_target, players.iter().map(|p| p.settings.side)),
+            players,
+            bots: vec![],
             win_target,
-            left,
-            right,
-
-            pressing_6: false,
-            cur_progress: 0.0,
+            model,
             last_time: None,
-            a: 0.0,
             end_time: None,
             effects: vec![],
+            debouncer: Default::default(),
+            stats: None,
+            series: None,
+            net: None,
+            log: vec![],
+            published: 0,
+            spectate: None,
             exit: false,
         }
     }
 
-    fn on_event(&mut self, event: &Event, now: SystemTime) {
-        if let Event::Key { key, pressed, .. } = event {
-            let pressed = *pressed;
-            match *key {
-                Key::A => {
-                    if self.pressing_a {
-                        if !pressed {
-                            self.pressing_a = false;
-                        }
-                    } else {
-                        if pressed {
-                            self.pressing_a = true;
-                            self.a += self.left_click.click(now);
-                        }
-                    }
-                }
-                Key::Num6 => {
-                    if self.pressing_6 {
-                        if !pressed {
-                            self.pressing_6 = false;
-                        }
-                    } else {
-                        if pressed {
-                            self.pressing_6 = true;
-                            self.a -= self.right_click.click(now);
-                        }
-                    }
+    /// Show the match streamed from the broadcast
+    pub(crate) fn watching(spectator: Spectator, watched: WatchedMatch) -> Self {
+        let mut ret = Self::new(watched.sim.win_target(), watched.sim.model(), watched.info.players);
+        ret.sim = watched.sim;
+        ret.start_time = Some(watched.go_time.saturating_sub(COUNTDOWN));
+        ret.spectate = Some(spectator);
+        ret
+    }
+
+    /// Let a bot play the side instead of its players, its clicks are not judged.
+    pub(crate) fn with_bot(mut self, side: Side, name: &str, profile: BotProfile) -> Self {
+        let color = self.side_players(side).next().map(|(_, p)| p.settings.color).unwrap_or([1.0, 1.0, 1.0]);
+        self.players.retain(|p| p.settings.side != side);
+        self.players.push(Player {
+            settings: PlayerSettings {
+                name: name.into(),
+                color,
+                side,
+                bindings: vec![],
+            },
+            bot: Some(profile),
+        });
+        self.sim = TugSim::new(self.model, self.win_target, self.players.iter().map(|p| p.settings.side));
+        self
+    }
+
+    pub(crate) fn with_series(mut self, series: Series) -> Self {
+        self.series = Some(series);
+        self
+    }
+
+    /// Play over the network, the host plays the left side.
+    ///
+    /// `start_time` is the agreed start in the host clock, the local clicks apply `input_delay` ticks later.
+    pub(crate) fn with_net(mut self, session: NetSession, start_time: Duration, input_delay: u64) -> Self {
+        let local = match session.role() {
+            NetRole::Host => Side::Left,
+            NetRole::Client => Side::Right,
+        };
+        let local_player = self.players.iter().position(|p| p.settings.side == local).expect("No local player");
+        let remote = self.players.iter().position(|p| p.settings.side != local).expect("No remote player");
+        self.start_time = Some(session.from_host(start_time));
+        self.net = Some(NetPlay {
+            rollback: Rollback::new(&self.sim, start_time + COUNTDOWN, input_delay, local_player, remote),
+            session,
+        });
+        self
+    }
+
+    /// The match has ended, over the network no late click can change it anymore
+    fn is_over(&self) -> bool {
+        match &self.net {
+            Some(net) => net.rollback.is_settled(&self.sim),
+            None => self.sim.is_over(),
+        }
+    }
+
+    /// Click in the local sim and log it for the broadcast
+    fn click(&mut self, player: usize, time: Duration, synthetic: bool) {
+        self.log.push(LogClick { tick: self.sim.ticks(), player, time, synthetic });
+        self.sim.click(player, time, synthetic);
+    }
+
+    /// Tell the spectators the new clicks
+    fn publish(&mut self, broadcast: &mut Broadcast) {
+        let log = std::mem::take(&mut self.log);
+        let (until, clicks) = match &self.net {
+            Some(net) => (net.rollback.settled_tick(), net.rollback.settled_clicks(self.published)),
+            None => (self.sim.ticks(), log),
+        };
+        self.published = until;
+        broadcast.publish(until, clicks);
+    }
+
+    /// Post the runs of the humans playing here, each peer posts its own player
+    fn submit(&self, leaderboard: &Leaderboard, go_time: Duration) {
+        let go_time = self.net.as_ref().map_or(go_time, |net| net.session.to_host(go_time));
+        for (i, player) in self.players.iter().enumerate() {
+            if player.bot.is_some() || player.settings.bindings.is_empty() {
+                continue;
+            }
+            let sim = &self.sim.players[i];
+            let duration = sim.clicks.last().map_or(Duration::ZERO, |x| x.saturating_sub(go_time));
+            if let Some(run) = RunSubmission::from_clicks(&player.settings.name, TUG_BOARD, &sim.clicks, duration, sim.synthetic) {
+                leaderboard.submit(run);
+            }
+        }
+    }
+
+    /// The side the progress is going to
+    fn winner(&self) -> Side {
+        self.sim.winner()
+    }
+
+    fn side_players(&self, side: Side) -> impl Iterator<Item=(usize, &Player)> {
+        self.players.iter().enumerate().filter(move |(_, p)| p.settings.side == side)
+    }
+
+    fn winner_text(&self) -> String {
+        let side = self.winner();
+        let mut players = self.side_players(side);
+        match (players.next(), players.next()) {
+            (Some((_, player)), None) => format!("{} Won!", player.settings.name),
+            _ => format!("{:?} Team Won!", side),
+        }
+    }
+
+    /// Draw the contributions and the stats of the side players
+    fn side_results(&self, ui: &mut Ui, side: Side, stats: &[(ClickStats, Verdict)]) {
+        let total: f32 = self.side_players(side).map(|(i, _)| self.sim.players[i].force).sum();
+        let alone = self.side_players(side).count() == 1;
+        ui.heading(format!("{:?}", side));
+        Grid::new(("contributions", side)).striped(true).show(ui, |ui| {
+            ui.label("Player");
+            ui.label("Clicks");
+            ui.label("Force");
+            ui.label("Verdict");
+            ui.end_row();
+            for (i, player) in self.side_players(side) {
+                let sim = &self.sim.players[i];
+                let share = if total > 0.0 { sim.force / total } else { 0.0 };
+                ui.label(RichText::new(&player.settings.name).color(color32(player.settings.color)));
+                ui.label(sim.clicks.len().to_string());
+                ui.label(format!("{:.1} ({:.0}%)", sim.force, share * 100.0));
+                if player.bot.is_some() {
+                    ui.label("Bot");
+                } else {
+                    verdict_label(ui, &stats[i].1);
                 }
-                _ => {}
+                ui.end_row();
             }
+        });
+        for (i, player) in self.side_players(side) {
+            CollapsingHeader::new(format!("{} Stats", player.settings.name))
+                .id_source(("player-stats", i))
+                .default_open(alone)
+                .show(ui, |ui| stats_panel(ui, &format!("player-stats-{}", i), &stats[i].0));
         }
     }
 }
 
 impl GameState for MulClickState {
-    fn start(&mut self, _: &mut StateData) {
-        self.start_time.replace(SystemTime::now());
+    fn start(&mut self, s: &mut StateData) {
+        let start_time = *self.start_time.get_or_insert(s.now());
+        self.debouncer = Debouncer::new(Duration::from_millis(s.window.settings.get().debounce_ms));
+        let go_time = start_time + COUNTDOWN;
+        self.bots = self.players.iter().enumerate()
+            .filter_map(|(i, p)| p.bot.clone().map(|profile| (i, Bot::new(profile, go_time))))
+            .collect();
+        if self.spectate.is_none() {
+            if let Some(mut broadcast) = s.window.world.try_fetch_mut::<Broadcast>() {
+                let dt = if self.net.is_some() { 1.0 / ROLLBACK_HZ as f32 } else { s.window.timestep.dt() };
+                let players = self.players.iter().map(|p| PlayerSettings { bindings: vec![], ..p.settings.clone() }).collect();
+                broadcast.begin(MatchInfo { players, dt }, self.sim.clone(), go_time, s.now());
+            }
+        }
+    }
+
+    fn fixed_update(&mut self, s: &mut StateData) {
+        // the network game ticks by the host clock and the watched one by the log in update
+        if self.net.is_none() && self.spectate.is_none() && s.now().saturating_sub(self.start_time.unwrap()) > COUNTDOWN {
+            self.sim.step(s.dt);
+        }
     }
 
     fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
-        (if self.exit || s.window.inputs.cur_frame_input.pressing.contains(&VirtualKeyCode::Escape) { Trans::Pop } else { Trans::None }, LoopState::POLL)
+        let go_time = self.start_time.unwrap() + COUNTDOWN;
+        let inputs = &s.window.inputs;
+        // the clicks of the frame with the player index and if it is synthetic, applied in the time order
+        let mut clicks: Vec<(Duration, usize, bool)> = vec![];
+        for event in inputs.frame_events() {
+            // track the releases before go too
+            let pressed = self.debouncer.accept(event);
+            if !pressed || event.time <= go_time || self.is_over() {
+                continue;
+            }
+            for (i, player) in self.players.iter().enumerate() {
+                let bindings = &player.settings.bindings;
+                if bindings.iter().any(|b| b.is_pressed_by(event, inputs.size_scale)) {
+                    clicks.push((event.time, i, false));
+                } else if bindings.iter().any(|b| b.is_synthetic_press_by(event)) {
+                    clicks.push((event.time, i, true));
+                }
+            }
+        }
+        if !self.sim.is_over() {
+            clicks.extend(self.bots.iter_mut()
+                .flat_map(|(i, bot)| bot.clicks_until(s.now()).into_iter().map(|time| (time, *i, false))));
+        }
+        clicks.sort_by_key(|x| x.0);
+        for (time, i, synthetic) in clicks {
+            match &mut self.net {
+                Some(net) => net.rollback.local_click(net.session.to_host(time), synthetic),
+                None => self.click(i, time, synthetic),
+            }
+        }
+        if let Some(net) = &mut self.net {
+            net.session.poll(s.now());
+            for msg in net.session.take_messages() {
+                match msg {
+                    Message::Inputs { until, clicks } => net.rollback.remote_inputs(&mut self.sim, until, clicks),
+                    Message::Checksum { tick, sum } => net.rollback.remote_checksum(tick, sum),
+                    _ => {}
+                }
+            }
+            let now = net.session.to_host(s.now());
+            net.rollback.advance(&mut self.sim, now);
+            for msg in net.rollback.outgoing(now) {
+                net.session.send(&msg);
+            }
+        }
+        if let Some(spectator) = &mut self.spectate {
+            if let Some(watched) = spectator.poll(s.now()) {
+                let spectator = self.spectate.take().unwrap();
+                return (Trans::Switch(Box::new(MulClickState::watching(spectator, watched))), LoopState::POLL);
+            }
+            spectator.advance(&mut self.sim);
+        }
+        if self.spectate.is_none() {
+            if let Some(mut broadcast) = s.window.world.try_fetch_mut::<Broadcast>() {
+                broadcast.poll(s.now());
+                self.publish(&mut broadcast);
+            }
+        }
+        self.log.clear();
+        if self.end_time.is_none() && self.is_over() {
+            self.end_time = Some(s.now());
+        }
+        if self.stats.is_none() && self.is_over() {
+            let bpm = s.window.settings.get().target_bpm as f64;
+            self.stats = Some(self.sim.players.iter()
+                .map(|x| (ClickStats::from_clicks(&x.clicks, bpm), analyze_clicks(&x.clicks, x.synthetic)))
+                .collect());
+            if self.spectate.is_none() {
+                self.submit(&s.window.leaderboard, go_time);
+            }
+        }
+        if let (Some(end_time), Some(_)) = (self.end_time, &self.series) {
+            if s.now().saturating_sub(end_time) >= ROUND_END_DELAY {
+                let mut series = self.series.take().unwrap();
+                series.record(self.winner(), end_time.saturating_sub(go_time));
+                return (Trans::Switch(series.next_state()), LoopState::POLL);
+            }
+        }
+        (if self.exit || s.window.inputs.action_held(Action::Back) { Trans::Pop } else { Trans::None }, LoopState::POLL)
     }
 
     fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
         egui::CentralPanel::default()
             .frame(Frame::none())
             .show(ctx, |ui| {
-                let now = SystemTime::now();
-                let sec = now.duration_since(self.start_time.unwrap()).unwrap().as_secs_f64();
+                let now = s.now();
+                let sec = now.saturating_sub(self.start_time.unwrap()).as_secs_f64();
                 let max_rect = ui.max_rect();
-                if sec > 3.0 {
+                if let Some(series) = &self.series {
+                    ui.painter().text(Pos2::new(max_rect.center().x, max_rect.min.y + 8.0), Align2::CENTER_TOP, series.status(),
+                                      FontId::proportional(20.0), Color32::WHITE);
+                }
+                if let Some(spectator) = &self.spectate {
+                    let text = match spectator.error() {
+                        Some(e) => format!("Disconnected: {}", e),
+                        None => format!("Watching, {:.0}s delay", spectator.delay().as_secs_f64()),
+                    };
+                    ui.painter().text(Pos2::new(max_rect.max.x - 8.0, max_rect.min.y + 8.0), Align2::RIGHT_TOP, text,
+                                      FontId::proportional(16.0), Color32::LIGHT_RED);
+                }
+                if let Some(net) = &self.net {
+                    let text = match (net.session.is_connected(), net.session.error()) {
+                        _ if net.rollback.desync().is_some() => format!("Out of sync at tick {}, the match is stopped", net.rollback.desync().unwrap()),
+                        (true, _) => net.session.rtt().map(|x| format!("Ping {:.0}ms, {} rollbacks", x.as_secs_f64() * 1000.0, net.rollback.rollbacks()))
+                            .unwrap_or_default(),
+                        (false, e) => format!("Disconnected: {}", e.unwrap_or("unknown")),
+                    };
+                    ui.painter().text(Pos2::new(max_rect.max.x - 8.0, max_rect.min.y + 8.0), Align2::RIGHT_TOP, text,
+                                      FontId::proportional(16.0), Color32::LIGHT_RED);
+                }
+                if sec > COUNTDOWN.as_secs_f64() {
                     if self.last_time.is_none() {
                         self.last_time.replace(now);
                     }
-                    if self.cur_progress.abs() < self.win_target {
-                        let now = SystemTime::now();
-                        let mut left_count = 0;
-                        let mut right_count = 0;
-                        s.window.egui_ctx.input(|is| {
-                            for x in &is.events {
-                                if let Event::Touch { pos, phase, .. } = x {
-                                    if *phase == TouchPhase::Start {
-                                        if pos.x < max_rect.width() / 2.0 {
-                                            left_count += 1;
-                                        } else if pos.x > max_rect.width() / 2.0 {
-                                            right_count += 1;
-                                        }
-                                    }
-                                }
-                                self.on_event(x, now);
-                            }
-                        });
-                        if left_count > 0 {
-                            self.a += self.left_click.click(now) * left_count as f32;
-                        }
-                        if right_count > 0 {
-                            self.a -= self.right_click.click(now) * right_count as f32;
-                        }
-                    } else {
-                        if let Some(end_time) = self.end_time {
-                            let dur = now.duration_since(end_time).unwrap().as_secs_f32();
+                    if let Some(end_time) = self.end_time {
+                        if !self.effects.is_empty() {
+                            let dur = now.saturating_sub(end_time).as_secs_f32();
                             self.effects[0].radius += s.dt * 300.0;
                             if dur > 0.25 {
                                 for i in 1..5 {
@@ -172,9 +376,8 @@ impl GameState for MulClickState {
                                 }
                             }
                         } else {
-                            self.end_time = Some(now);
-                            let center = [if self.cur_progress > 0.0 { max_rect.max.x - 100.0 } else { 100.0 },
-                                s.window.gpu.as_ref().unwrap().surface_cfg.height as f32 / 2.0];
+                            let center = [if self.winner() == Side::Left { max_rect.max.x - 100.0 } else { 100.0 },
+                                s.window.gpu.as_ref().map(|gpu| gpu.surface_cfg.height as f32).unwrap_or(max_rect.height()) / 2.0];
                             self.effects.push(InvertColorCircle {
                                 center,
                                 radius: 0.0,
@@ -199,58 +402,52 @@ impl GameState for MulClickState {
                             });
                         }
                     }
-                    self.cur_progress += s.dt * self.a;
+                    let progress = if self.net.is_some() || self.spectate.is_some() { self.sim.progress() } else { self.sim.interpolated(s.alpha()) };
                     let y = ui.max_rect().max.y - 48.0;
 
-                    let mid = (ui.max_rect().max.x / 2.0) * (1.0 + self.cur_progress / self.win_target);
-                    let mut right_bottom = Pos2::new(mid, y);
-                    let tint = Color32::from_rgba_premultiplied(255, 255, 255, 128);
-                    ui.allocate_ui_at_rect(Rect { min: Default::default(), max: right_bottom }, |ui| {
-                        ui.add(Image::new(self.left.id(), [mid, y]).tint(tint));
-                    });
-                    let mid_left = Pos2::new(mid, 0.0);
-                    right_bottom.x = ui.max_rect().max.x;
-                    ui.allocate_ui_at_rect(Rect { min: mid_left, max: right_bottom }, |ui| {
-                        ui.add(Image::new(self.right.id(), [max_rect.max.x - mid, y]).tint(tint));
-                    });
+                    let mid = (ui.max_rect().max.x / 2.0) * (1.0 + progress / self.win_target);
+                    // every side splits its part of the bar into the stripes of its players
+                    for (side, min_x, max_x) in [(Side::Left, 0.0, mid), (Side::Right, mid, max_rect.max.x)] {
+                        let count = self.side_players(side).count();
+                        for (row, (_, player)) in self.side_players(side).enumerate() {
+                            let height = y / count as f32;
+                            let rect = Rect::from_min_max(Pos2::new(min_x, height * row as f32), Pos2::new(max_x, height * (row + 1) as f32));
+                            let [r, g, b, _] = color32(player.settings.color).to_array();
+                            ui.painter().rect_filled(rect, 0.0, Color32::from_rgba_unmultiplied(r, g, b, 128));
+                        }
+                    }
                     ui.centered_and_justified(|ui| {
-                        ui.heading(format!("{:03.2} ({:.2})", self.cur_progress, self.a));
+                        ui.heading(format!("{:03.2} ({:.2})", progress, self.sim.force()));
                     });
                 }
 
                 ui.allocate_ui_at_rect(max_rect, |ui| {
                     ui.centered_and_justified(|ui| {
-                        if sec <= 4.0 {
-                            let mut r = 255;
-                            let text = if sec <= 1.0 {
-                                format!("Ready {:.02}", 3.0 - sec)
-                            } else if sec <= 2.0 {
-                                format!("Get   {:.02}", 3.0 - sec)
-                            } else if sec <= 3.0 {
-                                format!("Set   {:.02}", 3.0 - sec)
-                            } else {
-                                r = if sec >= 3.5 {
-                                    (((4.0 - sec) / 0.5) * 255.0) as u8
-                                } else {
-                                    255
-                                };
-                                format!("Go")
-                            };
-                            ui.add(Label::new(RichText::new(text).color(Color32::from_rgba_premultiplied(255, 255, 255, r)).heading()));
-                        }
+                        countdown_label(ui, sec);
                         if self.end_time.is_some() {
-                            if self.cur_progress > 0.0 {
-                                ui.add(Label::new(RichText::new("Left Won!").heading()));
-                            } else {
-                                ui.add(Label::new(RichText::new("Right Won!").heading()));
-                            }
+                            ui.add(Label::new(RichText::new(self.winner_text()).heading()));
                         }
                     });
                 });
+                if let Some(stats) = &self.stats {
+                    let half = max_rect.width() / 2.0;
+                    for (i, side) in [Side::Left, Side::Right].into_iter().enumerate() {
+                        let min = Pos2::new(max_rect.min.x + half * i as f32 + 16.0, max_rect.min.y + 16.0);
+                        ui.allocate_ui_at_rect(Rect::from_min_size(min, Vec2::new(half - 32.0, max_rect.height() / 2.0)), |ui| {
+                            ui.vertical(|ui| self.side_results(ui, side, stats));
+                        });
+                    }
+                }
             });
         Trans::None
     }
 
+    fn stop(&mut self, _: &mut StateData) {
+        if let Some(net) = &mut self.net {
+            net.session.leave();
+        }
+    }
+
     fn on_event(&mut self, s: Option<&mut StateData>, e: StateEvent) {
         if matches!(e, StateEvent::PostUiRender) {
             let s = s.unwrap();
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use egui::{Color32, Grid, RichText, Ui};
+use egui::plot::{Bar, BarChart, HLine, Legend, Line, MarkerShape, Plot, PlotPoints, Points};
+
+use crate::engine::{ClickStats, cps_at, histogram, intervals, PERCENTILES, ROLLING_WINDOW, rolling_cps, Verdict, VerdictLevel};
+
+/// The histogram bin width in seconds
+const HISTOGRAM_BIN: f64 = 0.01;
+/// The intervals longer are pauses and not in the histogram
+const HISTOGRAM_MAX: f64 = 0.5;
+
+/// Show the click stats in a grid, `id` should be unique in the ui.
+pub(crate) fn stats_panel(ui: &mut Ui, id: &str, stats: &ClickStats) {
+    Grid::new(id).striped(true).show(ui, |ui| {
+        ui.label("Clicks");
+        ui.label(stats.clicks.to_string());
+        ui.end_row();
+        ui.label("Mean interval");
+        ui.label(format!("{:.1}ms ({:.2} CPS)", stats.mean_interval * 1000.0, stats.mean_cps()));
+        ui.end_row();
+        ui.label("Stddev");
+        ui.label(format!("{:.2}ms", stats.stddev * 1000.0));
+        ui.end_row();
+        ui.label("Unstable rate");
+        ui.label(format!("{:.2}", stats.unstable_rate));
+        ui.end_row();
+        for (p, x) in PERCENTILES.iter().zip(stats.percentiles) {
+            ui.label(format!("P{}", p * 100.0));
+            ui.label(format!("{:.1}ms", x * 1000.0));
+            ui.end_row();
+        }
+        ui.label("Max 1s CPS");
+        ui.label(format!("{:.2}", stats.max_rolling_cps));
+        ui.end_row();
+        ui.label(format!("Streak {:.0}+ BPM", stats.target_bpm));
+        ui.label(format!("{} clicks in {:.2}s", stats.streak.clicks, stats.streak.duration.as_secs_f64()));
+        ui.end_row();
+    });
+}
+
+fn verdict_text(verdict: &Verdict) -> RichText {
+    let (text, color) = match verdict.level {
+        VerdictLevel::Clean => ("Clean", Color32::LIGHT_GREEN),
+        VerdictLevel::Suspicious => ("Suspicious", Color32::YELLOW),
+        VerdictLevel::Macro => ("Macro", Color32::RED),
+    };
+    RichText::new(format!("{} ({:.0}%)", text, verdict.confidence * 100.0)).color(color)
+}
+
+/// Show the verdict with the reasons on hover
+pub(crate) fn verdict_label(ui: &mut Ui, verdict: &Verdict) {
+    let label = ui.label(verdict_text(verdict));
+    if !verdict.reasons.is_empty() {
+        label.on_hover_text(verdict.reasons.join("\n"));
+    }
+}
+
+/// Draw the rolling cps since `start` until `now` with the max marker and the target bpm line.
+pub(crate) fn cps_chart(ui: &mut Ui, id: &str, clicks: &[Duration], start: Duration, now: Duration, target_bpm: f64, height: f32) {
+    let sec = |t: Duration| t.saturating_sub(start).as_secs_f64();
+    let mut cps: Vec<[f64; 2]> = rolling_cps(clicks, ROLLING_WINDOW).into_iter().map(|(t, x)| [sec(t), x]).collect();
+    let max = cps.iter().copied().max_by(|a, b| a[1].total_cmp(&b[1]));
+    cps.push([sec(now), cps_at(clicks, now, ROLLING_WINDOW)]);
+    Plot::new(id)
+        .height(height)
+        .include_x(0.0)
+        .include_y(0.0)
+        .include_y(target_bpm / 15.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .legend(Legend::default())
+        .label_formatter(|_, p| format!("{:.2}s\n{:.2} CPS\n{:.0} BPM", p.x, p.y, p.y * 15.0))
+        .show(ui, |plot| {
+            plot.line(Line::new(PlotPoints::from(cps)).name("CPS"));
+            plot.hline(HLine::new(target_bpm / 15.0).color(Color32::LIGHT_GREEN).name(format!("Target {:.0} BPM", target_bpm)));
+            if let Some(max) = max {
+                plot.points(Points::new(vec![max]).shape(MarkerShape::Diamond).radius(5.0).color(Color32::GOLD)
+                    .name(format!("Max {:.2} CPS", max[1])));
+            }
+        });
+}
+
+/// Draw the histogram of the intervals in milliseconds
+pub(crate) fn interval_histogram(ui: &mut Ui, id: &str, clicks: &[Duration], height: f32) {
+    let bins = histogram(&intervals(clicks), HISTOGRAM_BIN, HISTOGRAM_MAX);
+    let ms = HISTOGRAM_BIN * 1000.0;
+    let bars = bins.into_iter().enumerate()
+        .map(|(i, count)| Bar::new((i as f64 + 0.5) * ms, count as f64).width(ms))
+        .collect();
+    Plot::new(id)
+        .height(height)
+        .include_y(0.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .label_formatter(|_, p| format!("{:.0}ms", p.x))
+        .show(ui, |plot| {
+            plot.bar_chart(BarChart::new(bars).name("Intervals (ms)"));
+        });
+}
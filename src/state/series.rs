@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use egui::{Align2, Color32, Context, FontId, Frame, Grid, Pos2, RichText};
+
+use crate::engine::{Action, BotProfile, GameSettings, GameState, LoopState, PlayerSettings, Side, StateData, Trans, TugModel};
+use super::MulClickState;
+
+/// How long the intermission shows before the next round
+const INTERMISSION: Duration = Duration::from_secs(4);
+/// How long the round title slides in
+const SLIDE_IN: f32 = 0.6;
+
+fn other(side: Side) -> Side {
+    match side {
+        Side::Left => Side::Right,
+        Side::Right => Side::Left,
+    }
+}
+
+struct Round {
+    /// The team index, 0 for the team starting on the left
+    winner: usize,
+    duration: Duration,
+}
+
+/// The best-of-N rounds of the tug of war, moved from round to round by the states.
+pub(crate) struct Series {
+    best_of: u32,
+    swap_sides: bool,
+    win_target: f32,
+    physics: TugModel,
+    /// The players as they are in the first round
+    players: Vec<PlayerSettings>,
+    bot: Option<(Side, String, BotProfile)>,
+    rounds: Vec<Round>,
+}
+
+/// Start the tug of war from the settings, as a series if it has more than one round.
+pub(crate) fn start_match(settings: &GameSettings, players: Vec<PlayerSettings>, bot: Option<(Side, String, BotProfile)>) -> Box<dyn GameState> {
+    if settings.best_of > 1 {
+        let series = Series {
+            best_of: settings.best_of,
+            swap_sides: settings.swap_sides,
+            win_target: settings.win_target,
+            physics: settings.physics,
+            players,
+            bot,
+            rounds: vec![],
+        };
+        Box::new(series.next_round())
+    } else {
+        let mut state = MulClickState::new(settings.win_target, settings.physics, players);
+        if let Some((side, name, profile)) = bot {
+            state = state.with_bot(side, &name, profile);
+        }
+        Box::new(state)
+    }
+}
+
+impl Series {
+    fn wins(&self) -> [u32; 2] {
+        let mut wins = [0; 2];
+        for round in &self.rounds {
+            wins[round.winner] += 1;
+        }
+        wins
+    }
+
+    fn is_over(&self) -> bool {
+        self.wins().iter().any(|x| *x > self.best_of / 2)
+    }
+
+    /// Whether the teams play on the other sides in the round
+    fn swapped(&self, round: usize) -> bool {
+        self.swap_sides && round % 2 == 1
+    }
+
+    fn side_of(&self, team: usize, round: usize) -> Side {
+        let side = if team == 0 { Side::Left } else { Side::Right };
+        if self.swapped(round) { other(side) } else { side }
+    }
+
+    fn team_name(&self, team: usize) -> String {
+        let side = self.side_of(team, 0);
+        match &self.bot {
+            Some((bot_side, name, _)) if *bot_side == side => name.clone(),
+            _ => self.players.iter().filter(|x| x.side == side).map(|x| x.name.as_str()).collect::<Vec<_>>().join(" & "),
+        }
+    }
+
+    fn team_color(&self, team: usize) -> Color32 {
+        let side = self.side_of(team, 0);
+        let color = self.players.iter().find(|x| x.side == side).map(|x| x.color).unwrap_or([1.0, 1.0, 1.0]);
+        let to = |x: f32| (x * 255.0) as u8;
+        Color32::from_rgb(to(color[0]), to(color[1]), to(color[2]))
+    }
+
+    /// The line at the top of the round
+    pub(crate) fn status(&self) -> String {
+        let wins = self.wins();
+        format!("Round {} (Best of {})  {} {} - {} {}", self.rounds.len() + 1, self.best_of,
+                self.team_name(0), wins[0], wins[1], self.team_name(1))
+    }
+
+    /// Record the round the side won
+    pub(crate) fn record(&mut self, winner: Side, duration: Duration) {
+        let round = self.rounds.len();
+        let winner = (0..2).find(|x| self.side_of(*x, round) == winner).unwrap();
+        self.rounds.push(Round { winner, duration });
+    }
+
+    /// The state after the round recorded
+    pub(crate) fn next_state(self) -> Box<dyn GameState> {
+        if self.is_over() {
+            Box::new(SeriesSummaryState { series: self })
+        } else {
+            Box::new(IntermissionState { series: Some(self), start_time: None })
+        }
+    }
+
+    fn next_round(self) -> MulClickState {
+        let swapped = self.swapped(self.rounds.len());
+        let flip = |side| if swapped { other(side) } else { side };
+        let players = self.players.iter().cloned().map(|x| PlayerSettings { side: flip(x.side), ..x }).collect();
+        let mut state = MulClickState::new(self.win_target, self.physics, players);
+        if let Some((side, name, profile)) = &self.bot {
+            state = state.with_bot(flip(*side), name, profile.clone());
+        }
+        state.with_series(self)
+    }
+
+    /// The same series from the first round
+    fn rematch(&self) -> Series {
+        Series {
+            best_of: self.best_of,
+            swap_sides: self.swap_sides,
+            win_target: self.win_target,
+            physics: self.physics,
+            players: self.players.clone(),
+            bot: self.bot.clone(),
+            rounds: vec![],
+        }
+    }
+}
+
+/// Shows the score between the rounds
+pub(crate) struct IntermissionState {
+    series: Option<Series>,
+    start_time: Option<Duration>,
+}
+
+impl GameState for IntermissionState {
+    fn start(&mut self, s: &mut StateData) {
+        self.start_time = Some(s.now());
+    }
+
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        let inputs = &s.window.inputs;
+        if inputs.action_pressed(Action::Back) {
+            (Trans::Pop, LoopState::POLL)
+        } else if inputs.action_pressed(Action::Confirm) || s.now().saturating_sub(self.start_time.unwrap()) >= INTERMISSION {
+            (Trans::Switch(Box::new(self.series.take().unwrap().next_round())), LoopState::POLL)
+        } else {
+            (Trans::None, LoopState::POLL)
+        }
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let series = match &self.series {
+            Some(x) => x,
+            None => return Trans::None,
+        };
+        let sec = s.now().saturating_sub(self.start_time.unwrap()).as_secs_f32();
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                let rect = ui.max_rect();
+                let painter = ui.painter();
+                // the title slides in from the left and eases out
+                let t = (sec / SLIDE_IN).min(1.0);
+                let x = rect.center().x - (1.0 - t).powi(3) * rect.width();
+                let alpha = (t * 255.0) as u8;
+                let round = series.rounds.len();
+                painter.text(Pos2::new(x, rect.center().y - 80.0), Align2::CENTER_CENTER, format!("Round {}", round + 1),
+                             FontId::proportional(64.0), Color32::from_white_alpha(alpha));
+                for (team, wins) in series.wins().into_iter().enumerate() {
+                    let side = series.side_of(team, round);
+                    let (dx, align, text) = match side {
+                        Side::Left => (-40.0, Align2::RIGHT_CENTER, format!("{}  {}", series.team_name(team), wins)),
+                        Side::Right => (40.0, Align2::LEFT_CENTER, format!("{}  {}", wins, series.team_name(team))),
+                    };
+                    painter.text(Pos2::new(rect.center().x + dx, rect.center().y), align, text, FontId::proportional(40.0),
+                                 series.team_color(team).linear_multiply(t));
+                }
+                painter.text(rect.center(), Align2::CENTER_CENTER, "-", FontId::proportional(40.0), Color32::from_white_alpha(alpha));
+                if round > 0 && series.swapped(round) != series.swapped(round - 1) {
+                    painter.text(Pos2::new(rect.center().x, rect.center().y + 60.0), Align2::CENTER_CENTER, "Sides swap!",
+                                 FontId::proportional(28.0), Color32::YELLOW.linear_multiply(t));
+                }
+                let left = INTERMISSION.as_secs_f32() - sec;
+                painter.text(Pos2::new(rect.center().x, rect.max.y - 64.0), Align2::CENTER_CENTER,
+                             format!("Next round in {:.0}s, press Confirm to start now", left.ceil().max(0.0)),
+                             FontId::proportional(18.0), Color32::GRAY);
+            });
+        Trans::None
+    }
+}
+
+/// Shows the rounds and the winner when the series is over
+pub(crate) struct SeriesSummaryState {
+    series: Series,
+}
+
+impl GameState for SeriesSummaryState {
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        if s.window.inputs.action_pressed(Action::Back) {
+            (Trans::Pop, LoopState::POLL)
+        } else {
+            (Trans::None, LoopState::WAIT)
+        }
+    }
+
+    fn render(&mut self, _: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        let series = &self.series;
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    let wins = series.wins();
+                    let winner = if wins[0] > wins[1] { 0 } else { 1 };
+                    ui.heading(RichText::new(format!("{} won the series {} - {}!", series.team_name(winner), wins[winner], wins[1 - winner]))
+                        .color(series.team_color(winner)));
+                    Grid::new("series-rounds").striped(true).show(ui, |ui| {
+                        ui.label("Round");
+                        ui.label("Left");
+                        ui.label("Right");
+                        ui.label("Winner");
+                        ui.label("Time");
+                        ui.end_row();
+                        for (i, round) in series.rounds.iter().enumerate() {
+                            let left = if series.side_of(0, i) == Side::Left { 0 } else { 1 };
+                            ui.label((i + 1).to_string());
+                            ui.label(series.team_name(left));
+                            ui.label(series.team_name(1 - left));
+                            ui.label(RichText::new(series.team_name(round.winner)).color(series.team_color(round.winner)));
+                            ui.label(format!("{:.2}s", round.duration.as_secs_f64()));
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("Rematch").clicked() {
+                        ret = Trans::Switch(Box::new(series.rematch().next_round()));
+                    }
+                    if ui.button("Back").clicked() {
+                        ret = Trans::Pop;
+                    }
+                });
+            });
+        ret
+    }
+}
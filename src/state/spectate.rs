@@ -0,0 +1,140 @@
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use egui::{Color32, Context, DragValue, Frame, TextEdit};
+
+use crate::engine::{Action, Broadcast, GameState, LoopState, SPECTATE_PORT, Spectator, StateData, Trans};
+use super::MulClickState;
+
+/// The screen to broadcast the matches played here or watch the matches of another host.
+pub struct SpectateState {
+    port: u16,
+    address: String,
+    /// Watch the matches this many seconds late
+    delay: f32,
+    /// Waiting for the next match of the host
+    spectator: Option<Spectator>,
+    error: Option<String>,
+}
+
+impl Default for SpectateState {
+    fn default() -> Self {
+        Self {
+            port: SPECTATE_PORT,
+            address: format!("127.0.0.1:{}", SPECTATE_PORT),
+            delay: 0.0,
+            spectator: None,
+            error: None,
+        }
+    }
+}
+
+impl SpectateState {
+    fn watch(&mut self) {
+        let spectator = self.address.to_socket_addrs()
+            .map_err(anyhow::Error::from)
+            .and_then(|mut x| x.next().ok_or(anyhow!("No address for {}", self.address)))
+            .and_then(|addr| Spectator::connect(addr, Duration::from_secs_f32(self.delay)));
+        match spectator {
+            Ok(spectator) => {
+                self.spectator = Some(spectator);
+                self.error = None;
+            }
+            Err(e) => {
+                log::warn!("Watch {} failed for {:?}", self.address, e);
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+impl GameState for SpectateState {
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        let now = s.now();
+        if let Some(mut broadcast) = s.window.world.try_fetch_mut::<Broadcast>() {
+            broadcast.poll(now);
+        }
+        if let Some(spectator) = &mut self.spectator {
+            if let Some(watched) = spectator.poll(now) {
+                let spectator = self.spectator.take().unwrap();
+                return (Trans::Push(Box::new(MulClickState::watching(spectator, watched))), LoopState::POLL);
+            }
+        }
+        if s.window.inputs.action_pressed(Action::Back) {
+            return (Trans::Pop, LoopState::POLL);
+        }
+        (Trans::None, LoopState::POLL)
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Spectate");
+                    let broadcasting = s.window.world.try_fetch::<Broadcast>()
+                        .map(|x| (x.local_addr().map(|x| x.port()).unwrap_or(self.port), x.watchers()));
+                    match broadcasting {
+                        Some((port, watchers)) => {
+                            ui.label(format!("Broadcasting the matches here on port {}, {} watching", port, watchers));
+                            if ui.button("Stop Broadcast").clicked() {
+                                s.window.world.remove::<Broadcast>();
+                            }
+                        }
+                        None => {
+                            ui.horizontal(|ui| {
+                                ui.label("Port:");
+                                ui.add(DragValue::new(&mut self.port));
+                                if ui.button("Broadcast").on_hover_text("Let the others watch the matches played here").clicked() {
+                                    match Broadcast::new(self.port) {
+                                        Ok(broadcast) => {
+                                            s.window.world.insert(broadcast);
+                                            self.error = None;
+                                        }
+                                        Err(e) => {
+                                            log::warn!("Broadcast on {} failed for {:?}", self.port, e);
+                                            self.error = Some(e.to_string());
+                                        }
+                                    }
+                                }
+                            });
+                        }
+                    }
+                    ui.separator();
+                    match &self.spectator {
+                        Some(spectator) => {
+                            ui.label(format!("Watching {}, waiting for the next match...", self.address));
+                            if let Some(e) = spectator.error() {
+                                ui.colored_label(Color32::LIGHT_RED, e);
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.spectator = None;
+                            }
+                        }
+                        None => {
+                            ui.horizontal(|ui| {
+                                ui.label("Address:");
+                                ui.add(TextEdit::singleline(&mut self.address).desired_width(160.0));
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Delay:");
+                                ui.add(DragValue::new(&mut self.delay).clamp_range(0.0..=60.0).speed(0.1).suffix("s"));
+                                if ui.button("Watch").clicked() {
+                                    self.watch();
+                                }
+                            });
+                        }
+                    }
+                    if let Some(e) = &self.error {
+                        ui.colored_label(Color32::LIGHT_RED, e);
+                    }
+                    if ui.button("Back").clicked() {
+                        ret = Trans::Pop;
+                    }
+                });
+            });
+        ret
+    }
+}
@@ -0,0 +1,114 @@
+use egui::{Button, ComboBox, Context, Frame, Grid, TextEdit};
+
+use crate::engine::{Action, Binding, GameSettings, GameState, LoopState, PlayerSettings, RegionCapture, Side, StateData, Trans};
+use super::binding_text;
+use super::series::start_match;
+
+const MIN_PLAYERS: usize = 2;
+const MAX_PLAYERS: usize = 8;
+
+/// The screen to set up the players and teams of the team game.
+#[derive(Default)]
+pub struct TeamSetupState {
+    /// Waiting a key, mouse button or dragged touch region for the player
+    listening: Option<usize>,
+    capture: RegionCapture,
+}
+
+impl GameState for TeamSetupState {
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        if let Some(i) = self.listening {
+            let inputs = &s.window.inputs;
+            if let Some(binding) = inputs.pressed_binding().or_else(|| self.capture.update(inputs).map(Binding::Touch)) {
+                s.window.settings.update(|x| {
+                    let bindings = &mut x.players[i].bindings;
+                    if !bindings.contains(&binding) {
+                        bindings.push(binding);
+                    }
+                });
+                self.listening = None;
+            }
+            (Trans::None, LoopState::POLL)
+        } else if s.window.inputs.action_pressed(Action::Back) {
+            (Trans::Pop, LoopState::POLL)
+        } else {
+            (Trans::None, LoopState::POLL)
+        }
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Team Game");
+                    let settings = &mut s.window.settings;
+                    let mut removed_player = None;
+                    Grid::new("players").striped(true).show(ui, |ui| {
+                        for i in 0..settings.get().players.len() {
+                            settings.update(|x| {
+                                let player = &mut x.players[i];
+                                ui.add(TextEdit::singleline(&mut player.name).desired_width(80.0));
+                                ui.color_edit_button_rgb(&mut player.color);
+                                ComboBox::from_id_source(("side", i))
+                                    .selected_text(format!("{:?}", player.side))
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut player.side, Side::Left, "Left");
+                                        ui.selectable_value(&mut player.side, Side::Right, "Right");
+                                    });
+                            });
+                            let mut removed = None;
+                            for binding in &settings.get().players[i].bindings {
+                                if ui.button(binding_text(binding)).on_hover_text("Click to remove").clicked() {
+                                    removed = Some(*binding);
+                                }
+                            }
+                            if let Some(binding) = removed {
+                                settings.update(|x| x.players[i].bindings.retain(|b| *b != binding));
+                            }
+                            if self.listening == Some(i) {
+                                ui.label("Press a key or drag a region...");
+                            } else if ui.button("+").clicked() {
+                                self.listening = Some(i);
+                                self.capture.clear();
+                            }
+                            if settings.get().players.len() > MIN_PLAYERS && ui.button("Remove").clicked() {
+                                removed_player = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = removed_player {
+                        settings.update(|x| x.players.remove(i));
+                        self.listening = None;
+                    }
+                    let players = &settings.get().players;
+                    if players.len() < MAX_PLAYERS && ui.button("Add Player").clicked() {
+                        let side = if players.iter().filter(|x| x.side == Side::Left).count() * 2 > players.len() { Side::Right } else { Side::Left };
+                        let player = PlayerSettings {
+                            name: format!("P{}", players.len() + 1),
+                            color: [1.0, 1.0, 1.0],
+                            side,
+                            bindings: vec![],
+                        };
+                        settings.update(|x| x.players.push(player));
+                    }
+                    let players = &settings.get().players;
+                    let ready = [Side::Left, Side::Right].iter().all(|side| players.iter().any(|x| x.side == *side));
+                    if ui.add_enabled(ready, Button::new("Start")).on_disabled_hover_text("Both sides need a player").clicked() {
+                        let settings = settings.get();
+                        ret = Trans::Push(start_match(settings, settings.players.clone(), None));
+                    }
+                    if ui.button("Reset").clicked() {
+                        s.window.settings.update(|x| x.players = GameSettings::default().players);
+                        self.listening = None;
+                    }
+                    if ui.button("Back").clicked() {
+                        ret = Trans::Pop;
+                    }
+                });
+            });
+        ret
+    }
+}
@@ -0,0 +1,466 @@
+use std::time::Duration;
+
+use egui::{Button, Color32, ComboBox, Context, DragValue, Frame, Grid, RichText};
+use egui::plot::{HLine, Plot, Points};
+use kira::ClockSpeed;
+use kira::clock::{ClockHandle, ClockTime};
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use winit::event::MouseButton;
+
+use crate::engine::{Action, ClickStats, GameState, InputEventKind, LoopState, mean, metronome_tick, SessionRecord, StateData, stddev, Trans, unstable_rate};
+
+/// The beats of the metronome before the first note
+const COUNT_IN_BEATS: u32 = 4;
+
+/// The hit windows as the fraction of the note length, from the best
+const HIT_WINDOWS: [(Judgement, f64); 3] = [
+    (Judgement::Perfect, 0.1),
+    (Judgement::Great, 0.2),
+    (Judgement::Good, 0.35),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoteLength {
+    Quarter,
+    Eighth,
+    Sixteenth,
+}
+
+impl NoteLength {
+    pub const ALL: [NoteLength; 3] = [NoteLength::Quarter, NoteLength::Eighth, NoteLength::Sixteenth];
+
+    pub fn per_beat(&self) -> u32 {
+        match self {
+            NoteLength::Quarter => 1,
+            NoteLength::Eighth => 2,
+            NoteLength::Sixteenth => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoteLength::Quarter => "1/4",
+            NoteLength::Eighth => "1/8",
+            NoteLength::Sixteenth => "1/16",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl Judgement {
+    fn judge(offset: f64, note: f64) -> Judgement {
+        HIT_WINDOWS.iter()
+            .find(|(_, window)| offset.abs() <= window * note)
+            .map_or(Judgement::Miss, |x| x.0)
+    }
+
+    fn score(&self) -> u32 {
+        match self {
+            Judgement::Perfect => 300,
+            Judgement::Great => 100,
+            Judgement::Good => 50,
+            Judgement::Miss => 0,
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            Judgement::Perfect => Color32::LIGHT_BLUE,
+            Judgement::Great => Color32::LIGHT_GREEN,
+            Judgement::Good => Color32::YELLOW,
+            Judgement::Miss => Color32::RED,
+        }
+    }
+}
+
+/// The run in progress or finished
+struct TrainerRun {
+    start: Duration,
+    /// Seconds of one beat
+    beat: f64,
+    /// Seconds of one note
+    note: f64,
+    per_beat: u32,
+    notes: usize,
+    /// The seconds from the note to the tap, negative is early
+    offsets: Vec<Option<f64>>,
+    taps: Vec<Duration>,
+    /// Taps out of the windows or on the notes hit already
+    extra: usize,
+    /// How many synthetic key presses were dropped in the run, only counted for the verdict
+    synthetic: usize,
+    /// The next beat the metronome plays
+    next_beat: u32,
+    last: Option<Judgement>,
+    finished: bool,
+}
+
+impl TrainerRun {
+    fn new(start: Duration, bpm: f64, length: NoteLength, bars: u32) -> Self {
+        let beat = 60.0 / bpm;
+        let notes = (bars * 4 * length.per_beat()) as usize;
+        Self {
+            start,
+            beat,
+            note: beat / length.per_beat() as f64,
+            per_beat: length.per_beat(),
+            notes,
+            offsets: vec![None; notes],
+            taps: vec![],
+            extra: 0,
+            synthetic: 0,
+            next_beat: 0,
+            last: None,
+            finished: false,
+        }
+    }
+
+    /// Seconds since the first note
+    fn sec(&self, time: Duration) -> f64 {
+        time.as_secs_f64() - self.start.as_secs_f64() - self.beat * COUNT_IN_BEATS as f64
+    }
+
+    fn tap(&mut self, time: Duration) {
+        let sec = self.sec(time);
+        let idx = (sec / self.note).round();
+        if idx < 0.0 || idx >= self.notes as f64 {
+            return;
+        }
+        self.taps.push(time);
+        let offset = sec - idx * self.note;
+        let judgement = Judgement::judge(offset, self.note);
+        let hit = &mut self.offsets[idx as usize];
+        if judgement == Judgement::Miss || hit.is_some() {
+            self.extra += 1;
+        } else {
+            *hit = Some(offset);
+            self.last = Some(judgement);
+        }
+    }
+
+    fn judgements(&self) -> impl Iterator<Item=Judgement> + '_ {
+        self.offsets.iter().map(|x| x.map_or(Judgement::Miss, |x| Judgement::judge(x, self.note)))
+    }
+
+    /// The score of the notes passed over the max score of them, in `[0, 1]`
+    fn accuracy(&self, passed: usize) -> f64 {
+        if passed == 0 {
+            return 1.0;
+        }
+        let score: u32 = self.judgements().take(passed).map(|x| x.score()).sum();
+        score as f64 / (passed as u32 * Judgement::Perfect.score()) as f64
+    }
+
+    fn passed(&self, now: Duration) -> usize {
+        let sec = self.sec(now) - HIT_WINDOWS[2].1 * self.note;
+        if sec < 0.0 { 0 } else { ((sec / self.note) as usize + 1).min(self.notes) }
+    }
+
+    fn hit_offsets(&self) -> Vec<f64> {
+        self.offsets.iter().flatten().copied().collect()
+    }
+}
+
+/// Tap with the metronome and get scored by the offset from the grid.
+pub struct StreamTrainerState {
+    length: NoteLength,
+    bars: u32,
+    run: Option<TrainerRun>,
+    ticks: Option<[StaticSoundData; 2]>,
+    /// Ticks once a beat from the start of the run, the metronome plays on it
+    clock: Option<ClockHandle>,
+    /// Is the cursor over our buttons in the last render
+    over_button: bool,
+}
+
+impl Default for StreamTrainerState {
+    fn default() -> Self {
+        Self {
+            length: NoteLength::Sixteenth,
+            bars: 8,
+            run: None,
+            ticks: None,
+            clock: None,
+            over_button: false,
+        }
+    }
+}
+
+impl StreamTrainerState {
+    /// Start the run and the clock of its metronome, the taps are scored against the beats heard after the latency
+    fn start_run(&mut self, s: &mut StateData, now: Duration) {
+        let bpm = s.window.settings.get().trainer_bpm as f64;
+        let latency = Duration::from_millis(s.window.settings.get().metronome_latency_ms);
+        self.run = Some(TrainerRun::new(now + latency, bpm, self.length, self.bars));
+        // dropping the clock drops the beats scheduled on it
+        self.clock = None;
+        if let (Some(al), Some(_)) = (&mut s.window.audio, &self.ticks) {
+            match al.manager.add_clock(ClockSpeed::TicksPerMinute(bpm)) {
+                Ok(clock) => {
+                    if let Err(e) = clock.start() {
+                        log::warn!("Start metronome failed for {:?}", e);
+                    }
+                    self.clock = Some(clock);
+                }
+                Err(e) => log::warn!("Add metronome clock failed for {:?}", e),
+            }
+        }
+    }
+
+    fn finish(&mut self, s: &mut StateData) {
+        if let Some(run) = &mut self.run {
+            run.finished = true;
+            // the stats take the bpm of the 1/16 notes
+            let stream_bpm = 15.0 * run.per_beat as f64 / run.beat;
+            let name = format!("Stream {:.0} BPM {}", 60.0 / run.beat, self.length.name());
+            let max_cps = ClickStats::from_clicks(&run.taps, stream_bpm).max_rolling_cps;
+            if let Some(record) = SessionRecord::from_clicks(name, &run.taps, max_cps, run.synthetic) {
+                s.window.history.push(record);
+            }
+        }
+    }
+}
+
+impl GameState for StreamTrainerState {
+    fn start(&mut self, s: &mut StateData) {
+        if s.window.audio.is_some() {
+            self.ticks = Some([metronome_tick(true), metronome_tick(false)]);
+        }
+    }
+
+    fn update(&mut self, s: &mut StateData) -> (Trans, LoopState) {
+        let now = s.now();
+        let inputs = &mut s.window.inputs;
+        let back = inputs.action_pressed(Action::Back);
+        let events: Vec<_> = inputs.drain_events().collect();
+        let mut done = false;
+        if let Some(run) = self.run.as_mut().filter(|x| !x.finished) {
+            for event in events {
+                let tapped = match event.kind {
+                    InputEventKind::Key { pressed: true, repeat: false, synthetic: true, .. } => {
+                        if !s.window.inputs.action_synthetic_by(Action::Back, &event) {
+                            run.synthetic += 1;
+                        }
+                        false
+                    }
+                    InputEventKind::Key { pressed: true, repeat: false, synthetic: false, .. } => !s.window.inputs.action_pressed_by(Action::Back, &event),
+                    InputEventKind::Mouse { button, pressed: true } => !self.over_button || button != MouseButton::Left,
+                    _ => false
+                };
+                if tapped {
+                    run.tap(event.time);
+                }
+            }
+            // the beats are scheduled on the clock a beat ahead, its tick 0 is the first beat
+            if let (Some(al), Some(ticks), Some(clock)) = (&mut s.window.audio, &self.ticks, &self.clock) {
+                let beats = COUNT_IN_BEATS + run.notes as u32 / run.per_beat;
+                while run.next_beat < beats && run.next_beat as u64 <= clock.time().ticks + 1 {
+                    let start_time = ClockTime { clock: clock.id(), ticks: run.next_beat as u64 };
+                    let tick = ticks[if run.next_beat % 4 == 0 { 0 } else { 1 }].with_settings(StaticSoundSettings::new().start_time(start_time));
+                    if let Err(e) = al.manager.play(tick) {
+                        log::warn!("Play metronome failed for {:?}", e);
+                    }
+                    run.next_beat += 1;
+                }
+            }
+            done = run.passed(now) >= run.notes;
+        }
+        if done {
+            self.finish(s);
+        }
+        if back {
+            if matches!(&self.run, Some(x) if !x.finished) {
+                self.run = None;
+                self.clock = None;
+            } else {
+                return (Trans::Pop, LoopState::POLL);
+            }
+        }
+        (Trans::None, LoopState::POLL)
+    }
+
+    fn render(&mut self, s: &mut StateData, ctx: &Context) -> Trans {
+        let mut ret = Trans::None;
+        let now = s.now();
+        egui::CentralPanel::default()
+            .frame(Frame::none())
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Stream Trainer");
+                    match &self.run {
+                        None => {
+                            Grid::new("trainer-setup").show(ui, |ui| {
+                                ui.label("BPM");
+                                s.window.settings.update(|x| ui.add(DragValue::new(&mut x.trainer_bpm).clamp_range(60.0..=400.0)));
+                                ui.end_row();
+                                ui.label("Latency (ms)");
+                                s.window.settings.update(|x| ui.add(DragValue::new(&mut x.metronome_latency_ms).clamp_range(0..=500)));
+                                ui.end_row();
+                                ui.label("Note");
+                                ComboBox::from_id_source("trainer-note")
+                                    .selected_text(self.length.name())
+                                    .show_ui(ui, |ui| {
+                                        for length in NoteLength::ALL {
+                                            ui.selectable_value(&mut self.length, length, length.name());
+                                        }
+                                    });
+                                ui.end_row();
+                                ui.label("Bars");
+                                ui.add(DragValue::new(&mut self.bars).clamp_range(1..=64));
+                                ui.end_row();
+                            });
+                            let start = ui.add(Button::new(RichText::new("Start").heading()));
+                            if start.clicked() || s.window.inputs.action_pressed(Action::Confirm) {
+                                self.start_run(s, now);
+                            }
+                            if ui.button("Back").clicked() {
+                                ret = Trans::Pop;
+                            }
+                            self.over_button = start.hovered();
+                        }
+                        Some(run) if !run.finished => {
+                            let beat = (run.sec(now) / run.beat).floor();
+                            if beat < 0.0 {
+                                ui.heading(format!("{}", -beat as i64));
+                            } else {
+                                let passed = run.passed(now);
+                                ui.heading(format!("{} / {}", passed, run.notes));
+                                ui.label(format!("Accuracy: {:.2}%", run.accuracy(passed) * 100.0));
+                            }
+                            if let Some(last) = run.last {
+                                ui.label(RichText::new(format!("{:?}", last)).color(last.color()).heading());
+                            }
+                            ui.label("Tap with the metronome, back to stop");
+                            self.over_button = false;
+                        }
+                        Some(run) => {
+                            let offsets = run.hit_offsets();
+                            let mut counts = [0; 4];
+                            for x in run.judgements() {
+                                counts[x as usize] += 1;
+                            }
+                            let ms: Vec<_> = offsets.iter().map(|x| x * 1000.0).collect();
+                            let early = offsets.iter().filter(|x| **x < 0.0).count();
+                            ui.heading(format!("Accuracy: {:.2}%", run.accuracy(run.notes) * 100.0));
+                            Grid::new("trainer-result").striped(true).show(ui, |ui| {
+                                for (judgement, count) in [Judgement::Perfect, Judgement::Great, Judgement::Good, Judgement::Miss].iter().zip(counts) {
+                                    ui.label(RichText::new(format!("{:?}", judgement)).color(judgement.color()));
+                                    ui.label(count.to_string());
+                                    ui.end_row();
+                                }
+                                ui.label("Extra taps");
+                                ui.label(run.extra.to_string());
+                                ui.end_row();
+                                ui.label("Early / Late");
+                                ui.label(format!("{} / {}", early, offsets.len() - early));
+                                ui.end_row();
+                                ui.label("Mean offset");
+                                ui.label(format!("{:+.2}ms", mean(&ms)));
+                                ui.end_row();
+                                ui.label("Unstable rate");
+                                ui.label(format!("{:.2}", unstable_rate(stddev(&offsets))));
+                                ui.end_row();
+                            });
+                            let points: Vec<_> = run.offsets.iter().enumerate()
+                                .filter_map(|(i, x)| x.map(|x| [i as f64, x * 1000.0]))
+                                .collect();
+                            let note_ms = run.note * 1000.0;
+                            Plot::new("trainer-offsets")
+                                .height(ui.available_height() / 2.0)
+                                .include_y(-HIT_WINDOWS[2].1 * note_ms)
+                                .include_y(HIT_WINDOWS[2].1 * note_ms)
+                                .label_formatter(|_, p| format!("Note {:.0}\n{:+.1}ms", p.x, p.y))
+                                .show(ui, |plot| {
+                                    for (judgement, window) in HIT_WINDOWS {
+                                        plot.hline(HLine::new(window * note_ms).color(judgement.color()));
+                                        plot.hline(HLine::new(-window * note_ms).color(judgement.color()));
+                                    }
+                                    plot.points(Points::new(points).radius(2.0).name("Offset (ms)"));
+                                });
+                            if ui.button("Retry").clicked() {
+                                self.start_run(s, now);
+                            }
+                            if ui.button("Back").clicked() {
+                                self.run = None;
+                            }
+                        }
+                    }
+                });
+            });
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One bar of the quarter notes at 60 BPM, the first note is at 14s
+    fn run() -> TrainerRun {
+        TrainerRun::new(Duration::from_secs(10), 60.0, NoteLength::Quarter, 1)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn judges_by_the_note_length() {
+        assert_eq!(Judgement::judge(0.0, 1.0), Judgement::Perfect);
+        assert_eq!(Judgement::judge(-0.1, 1.0), Judgement::Perfect);
+        assert_eq!(Judgement::judge(0.15, 1.0), Judgement::Great);
+        assert_eq!(Judgement::judge(-0.3, 1.0), Judgement::Good);
+        assert_eq!(Judgement::judge(0.4, 1.0), Judgement::Miss);
+        assert_eq!(Judgement::judge(0.04, 0.25), Judgement::Great);
+    }
+
+    #[test]
+    fn counts_in_before_the_first_note() {
+        let run = run();
+        assert_eq!(run.notes, 4);
+        assert_eq!(run.sec(ms(10_000)), -4.0);
+        assert_eq!(run.sec(ms(14_000)), 0.0);
+        assert_eq!(run.passed(ms(14_000)), 0);
+        assert_eq!(run.passed(ms(14_400)), 1);
+        assert_eq!(run.passed(ms(16_400)), 3);
+        assert_eq!(run.passed(ms(100_000)), 4);
+    }
+
+    #[test]
+    fn scores_the_taps() {
+        let mut run = run();
+        // before the first note and after the last
+        run.tap(ms(13_000));
+        run.tap(ms(18_000));
+        assert!(run.taps.is_empty());
+
+        run.tap(ms(14_000));
+        assert_eq!(run.last, Some(Judgement::Perfect));
+        run.tap(ms(14_850));
+        assert_eq!(run.last, Some(Judgement::Great));
+        run.tap(ms(16_300));
+        assert_eq!(run.last, Some(Judgement::Good));
+        // the note is hit already
+        run.tap(ms(16_320));
+        // out of the windows of the last note
+        run.tap(ms(16_600));
+        assert_eq!(run.last, Some(Judgement::Good));
+
+        assert_eq!(run.taps.len(), 5);
+        assert_eq!(run.extra, 2);
+        let offsets: Vec<_> = run.offsets.iter().map(|x| x.map(|x| (x * 1000.0).round() as i64)).collect();
+        assert_eq!(offsets, [Some(0), Some(-150), Some(300), None]);
+        assert_eq!(run.judgements().collect::<Vec<_>>(), [Judgement::Perfect, Judgement::Great, Judgement::Good, Judgement::Miss]);
+
+        assert_eq!(run.accuracy(0), 1.0);
+        assert_eq!(run.accuracy(2), 400.0 / 600.0);
+        assert_eq!(run.accuracy(4), 450.0 / 1200.0);
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use andy_clicker_core::engine::*;
+use andy_clicker_core::state::*;
+use winit::event::VirtualKeyCode;
+
+fn tap(app: &mut HeadlessApplication, key: VirtualKeyCode) {
+    app.press_key(key);
+    app.step(0.05);
+    app.release_key(key);
+    app.step(0.05);
+}
+
+#[test]
+fn steps_the_states() {
+    let mut app = HeadlessApplication::new(MainMenu::default());
+    app.step(0.016);
+    assert!(app.top::<MainMenu>().is_some());
+
+    tap(&mut app, VirtualKeyCode::S);
+    assert_eq!(app.state_count(), 2);
+    assert!(app.top::<ClickState>().is_some());
+    for _ in 0..10 {
+        tap(&mut app, VirtualKeyCode::A);
+    }
+    // not bound to the hits
+    tap(&mut app, VirtualKeyCode::Q);
+    app.press_key(VirtualKeyCode::Escape);
+    app.step(0.016);
+    assert_eq!(app.state_count(), 1);
+    assert!(app.top::<MainMenu>().is_some());
+    assert!(app.is_running());
+
+    let sessions = app.window().history.sessions();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].clicks, 10);
+    assert_eq!(sessions[0].intervals.len(), 9);
+    assert!(sessions[0].intervals.iter().all(|x| (x - 0.1).abs() < 1e-3), "{:?}", sessions[0].intervals);
+}
+
+#[test]
+fn steps_at_the_clock() {
+    let mut app = HeadlessApplication::new(MainMenu::default());
+    app.step_to(Duration::from_secs(3));
+    assert_eq!(app.clock().now(), Duration::from_secs(3));
+    app.step(0.5);
+    assert_eq!(app.clock().now(), Duration::from_millis(3500));
+    // the clock never goes back
+    app.step_to(Duration::from_secs(1));
+    assert_eq!(app.clock().now(), Duration::from_millis(3500));
+}
+
+#[test]
+fn counts_the_synthetic_presses() {
+    let mut app = HeadlessApplication::new(MainMenu::default());
+    app.step(0.016);
+    tap(&mut app, VirtualKeyCode::S);
+    for _ in 0..10 {
+        tap(&mut app, VirtualKeyCode::A);
+        for pressed in [true, false] {
+            app.input(InputEventKind::Key { key: VirtualKeyCode::A, pressed, repeat: false, synthetic: true });
+            app.step(0.01);
+        }
+    }
+    app.press_key(VirtualKeyCode::Escape);
+    app.step(0.016);
+
+    let sessions = app.window().history.sessions();
+    assert_eq!(sessions[0].clicks, 10);
+    let verdict = sessions[0].verdict.as_ref().unwrap();
+    assert!(!verdict.is_clean());
+    assert!(verdict.reasons.iter().any(|x| x.starts_with("10 synthetic")), "{:?}", verdict.reasons);
+}
@@ -0,0 +1,280 @@
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use andy_clicker_core::engine::*;
+
+/// The client clock is this far behind the host one
+const CLOCK_OFFSET: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn peer(name: &str, color: [f32; 3]) -> PeerInfo {
+    PeerInfo { name: name.into(), color }
+}
+
+/// A host on a free port and a client connected to it over localhost
+fn pair() -> (NetSession, NetSession) {
+    let host = NetSession::host(0, peer("Host", [1.0, 0.0, 0.0])).unwrap();
+    let port = host.local_addr().unwrap().port();
+    let client = NetSession::connect(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), peer("Guest", [0.0, 0.0, 1.0])).unwrap();
+    (host, client)
+}
+
+/// Poll both with their own clocks until the condition
+fn poll_until(host: &mut NetSession, client: &mut NetSession, start: Instant, mut done: impl FnMut(&mut NetSession, &mut NetSession) -> bool) {
+    while !done(host, client) {
+        assert!(start.elapsed() < TIMEOUT, "Timed out, host {:?}, client {:?}", host.error(), client.error());
+        host.poll(start.elapsed() + CLOCK_OFFSET);
+        client.poll(start.elapsed());
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// The messages the game handles
+fn game_messages() -> Vec<Message> {
+    let mut sim = TugSim::new(TugModel::Friction, 50.0, [Side::Left, Side::Right]);
+    sim.click(0, Duration::from_millis(10), false);
+    sim.step(1.0 / 240.0);
+    sim.click(1, Duration::from_millis(12), true);
+    vec![
+        Message::Start { start_time: Duration::from_millis(12345), win_target: 50.0, physics: TugModel::CatchUp, input_delay: 5 },
+        Message::Inputs { until: 42, clicks: vec![TickClick { tick: 40, time: Duration::from_micros(166_667), synthetic: false }] },
+        Message::Watch { version: PROTOCOL_VERSION },
+        Message::Snapshot {
+            info: MatchInfo { players: GameSettings::default().players, dt: 1.0 / 240.0 },
+            go_in: Duration::from_secs(3),
+            sim: sim.snapshot(),
+        },
+        Message::Events { until: 7, clicks: vec![LogClick { tick: 3, player: 1, time: Duration::from_millis(20), synthetic: true }] },
+    ]
+}
+
+/// Both ends of a tcp connection over localhost
+fn connections() -> (Connection, Connection) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (Connection::new(server).unwrap(), Connection::new(client).unwrap())
+}
+
+#[test]
+fn messages_round_trip() {
+    let (mut a, mut b) = connections();
+    let mut msgs = vec![
+        Message::Hello { version: PROTOCOL_VERSION, peer: peer("Host", [0.1, 0.2, 0.3]) },
+        Message::Welcome { peer: peer("Guest", [0.4, 0.5, 0.6]) },
+        Message::Ping { client_time: Duration::from_nanos(123_456_789) },
+        Message::Pong { client_time: Duration::from_nanos(123_456_789), host_time: Duration::from_secs(99) },
+        Message::Lobby { peer: peer("Guest", [0.0, 1.0, 0.0]), ready: true },
+    ];
+    msgs.extend(game_messages());
+    msgs.push(Message::Leave);
+    for msg in &msgs {
+        a.send(msg).unwrap();
+    }
+    let start = Instant::now();
+    let mut received = vec![];
+    while received.len() < msgs.len() {
+        assert!(start.elapsed() < TIMEOUT);
+        a.flush().unwrap();
+        received.extend(b.recv().unwrap());
+    }
+    assert_eq!(received, msgs);
+    assert_eq!(a.backlog(), 0);
+    drop(a);
+    let start = Instant::now();
+    while b.recv().is_ok() {
+        assert!(start.elapsed() < TIMEOUT);
+    }
+}
+
+#[test]
+fn send_never_blocks() {
+    let (mut a, _b) = connections();
+    let msg = Message::Events {
+        until: u64::MAX,
+        clicks: vec![LogClick { tick: 0, player: 0, time: Duration::ZERO, synthetic: false }; 1000],
+    };
+    // the peer never reads, the send fails once the backlog is too large
+    let start = Instant::now();
+    let mut sent = 0;
+    while a.send(&msg).is_ok() {
+        sent += 1;
+        assert!(start.elapsed() < TIMEOUT, "Sent {} messages", sent);
+    }
+    assert!(sent > 0);
+    assert!(a.backlog() > 0);
+}
+
+#[test]
+fn session_syncs_over_localhost() {
+    let (mut host, mut client) = pair();
+    assert_eq!(host.role(), NetRole::Host);
+    assert_eq!(client.role(), NetRole::Client);
+    let start = Instant::now();
+    poll_until(&mut host, &mut client, start, |host, client| host.is_synced() && client.is_synced());
+
+    // hello and welcome
+    assert_eq!(host.peer(), Some(&peer("Guest", [0.0, 0.0, 1.0])));
+    assert_eq!(client.peer(), Some(&peer("Host", [1.0, 0.0, 0.0])));
+
+    // the client knows the host clock within the round trip
+    let rtt = client.rtt().unwrap().as_secs_f64() + 0.01;
+    let now = start.elapsed();
+    let error = client.to_host(now).as_secs_f64() - (now + CLOCK_OFFSET).as_secs_f64();
+    assert!(error.abs() <= rtt, "Offset error {}s, rtt {}s", error, rtt);
+    assert!((client.from_host(now + CLOCK_OFFSET).as_secs_f64() - now.as_secs_f64()).abs() <= rtt);
+
+    // the lobby
+    client.set_local(peer("Guest", [0.0, 1.0, 0.0]), true);
+    host.set_local(peer("Host", [1.0, 0.0, 0.0]), true);
+    poll_until(&mut host, &mut client, start, |host, client| host.all_ready() && client.all_ready());
+    assert_eq!(host.peer(), Some(&peer("Guest", [0.0, 1.0, 0.0])));
+
+    // the game messages pass the session
+    for msg in game_messages() {
+        client.send(&msg);
+        host.send(&msg);
+    }
+    let mut from_client = vec![];
+    let mut from_host = vec![];
+    poll_until(&mut host, &mut client, start, |host, client| {
+        from_client.extend(host.take_messages());
+        from_host.extend(client.take_messages());
+        from_client.len() >= game_messages().len() && from_host.len() >= game_messages().len()
+    });
+    assert_eq!(from_client, game_messages());
+    assert_eq!(from_host, game_messages());
+
+    client.leave();
+    poll_until(&mut host, &mut client, start, |host, _| !host.is_connected());
+    assert_eq!(host.error(), Some("The peer left"));
+}
+
+#[test]
+fn refuses_other_versions() {
+    let mut host = NetSession::host(0, peer("Host", [1.0, 0.0, 0.0])).unwrap();
+    let port = host.local_addr().unwrap().port();
+    let mut conn = Connection::new(TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap()).unwrap();
+    conn.send(&Message::Hello { version: PROTOCOL_VERSION + 1, peer: peer("Old", [0.0; 3]) }).unwrap();
+    let start = Instant::now();
+    while host.error().is_none() {
+        assert!(start.elapsed() < TIMEOUT);
+        host.poll(start.elapsed());
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(host.peer().is_none());
+}
+
+/// A fake host the spectator connects to, and the spectator
+fn watch() -> (Connection, Spectator) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let spectator = Spectator::connect(listener.local_addr().unwrap(), Duration::ZERO).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    (Connection::new(stream).unwrap(), spectator)
+}
+
+fn info() -> MatchInfo {
+    MatchInfo { players: GameSettings::default().players, dt: 1.0 / 240.0 }
+}
+
+fn snapshot() -> Message {
+    let info = info();
+    let sim = TugSim::new(TugModel::Accumulate, 50.0, info.players.iter().map(|x| x.side));
+    Message::Snapshot { info, go_in: Duration::ZERO, sim: sim.snapshot() }
+}
+
+/// Poll the spectator until it has the match or fails
+fn watched(spectator: &mut Spectator) -> Option<WatchedMatch> {
+    let start = Instant::now();
+    while spectator.error().is_none() {
+        assert!(start.elapsed() < TIMEOUT);
+        if let Some(watched) = spectator.poll(start.elapsed()) {
+            return Some(watched);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    None
+}
+
+#[test]
+fn spectator_refuses_bad_log() {
+    let bad = [
+        Message::Events { until: 10, clicks: vec![LogClick { tick: 1, player: 7, time: Duration::ZERO, synthetic: false }] },
+        Message::Events { until: u64::MAX, clicks: vec![] },
+        Message::Events { until: 10, clicks: vec![LogClick { tick: 5000, player: 0, time: Duration::ZERO, synthetic: false }] },
+    ];
+    for msg in bad {
+        let (mut host, mut spectator) = watch();
+        host.send(&snapshot()).unwrap();
+        let mut watched = watched(&mut spectator).expect("No match");
+        host.send(&msg).unwrap();
+        assert!(self::watched(&mut spectator).is_none());
+        assert!(spectator.error().is_some());
+        spectator.advance(&mut watched.sim);
+        assert_eq!(watched.sim.ticks(), 0);
+    }
+
+    // the players must match the sim
+    let (mut host, mut spectator) = watch();
+    let sim = TugSim::new(TugModel::Accumulate, 50.0, [Side::Left]);
+    host.send(&Message::Snapshot { info: info(), go_in: Duration::ZERO, sim: sim.snapshot() }).unwrap();
+    assert!(watched(&mut spectator).is_none());
+
+    // the good log plays
+    let (mut host, mut spectator) = watch();
+    host.send(&snapshot()).unwrap();
+    let mut watched = watched(&mut spectator).expect("No match");
+    host.send(&Message::Events { until: 240, clicks: vec![LogClick { tick: 0, player: 1, time: Duration::ZERO, synthetic: false }] }).unwrap();
+    let start = Instant::now();
+    while watched.sim.ticks() < 240 {
+        assert!(start.elapsed() < TIMEOUT);
+        spectator.poll(start.elapsed());
+        spectator.advance(&mut watched.sim);
+    }
+    assert_eq!(watched.sim.players[1].clicks.len(), 1);
+}
+
+#[test]
+fn stalled_spectator_is_dropped() {
+    let mut broadcast = Broadcast::new(0).unwrap();
+    let port = broadcast.local_addr().unwrap().port();
+    // it asks for the matches and never reads again
+    let mut stalled = Connection::new(TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap()).unwrap();
+    stalled.send(&Message::Watch { version: PROTOCOL_VERSION }).unwrap();
+    let start = Instant::now();
+    while broadcast.watchers() == 0 {
+        assert!(start.elapsed() < TIMEOUT);
+        broadcast.poll(start.elapsed());
+    }
+
+    let info = info();
+    let sim = TugSim::new(TugModel::Accumulate, 1e9, info.players.iter().map(|x| x.side));
+    broadcast.begin(info, sim, Duration::ZERO, start.elapsed());
+    let mut tick = 0;
+    while broadcast.watchers() > 0 {
+        assert!(start.elapsed() < TIMEOUT, "The broadcast blocked at tick {}", tick);
+        let clicks = (0..100).map(|i| LogClick { tick, player: i % 2, time: Duration::from_micros(tick * 4167 + i as u64), synthetic: false }).collect();
+        tick += 1;
+        broadcast.publish(tick, clicks);
+        broadcast.poll(start.elapsed());
+    }
+}
+
+#[test]
+fn discovery_shares_the_port() {
+    // two processes on the machine both hear the host
+    let mut first = Discovery::new().unwrap();
+    let mut second = Discovery::new().unwrap();
+    let mut beacon = Beacon::new("Hosty".into(), 12345).unwrap();
+    let start = Instant::now();
+    let heard = |discovery: &Discovery| discovery.hosts().iter().any(|x| x.name == "Hosty" && x.addr.port() == 12345);
+    while !heard(&first) || !heard(&second) {
+        assert!(start.elapsed() < TIMEOUT, "{:?} {:?}", first.hosts(), second.hosts());
+        beacon.poll(start.elapsed());
+        first.poll(start.elapsed());
+        second.poll(start.elapsed());
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    second.poll(start.elapsed() + Duration::from_secs(10));
+    assert!(second.hosts().is_empty());
+}
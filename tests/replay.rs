@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use andy_clicker_core::engine::*;
+use andy_clicker_core::state::*;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+/// Open the click test, hit the Click button and the key and go back
+fn play(app: &mut HeadlessApplication) {
+    app.step(0.016);
+    app.press_key(VirtualKeyCode::S);
+    app.step(0.016);
+    app.release_key(VirtualKeyCode::S);
+    for _ in 0..10 {
+        app.step(0.016);
+    }
+    // the Click button under the Reset one is only counted by egui
+    app.move_cursor(800.0, 360.0);
+    app.step(0.016);
+    for _ in 0..3 {
+        app.press_mouse(MouseButton::Left);
+        app.step(0.05);
+        app.release_mouse(MouseButton::Left);
+        app.step(0.05);
+    }
+    for _ in 0..2 {
+        app.press_key(VirtualKeyCode::A);
+        app.step(0.05);
+        app.release_key(VirtualKeyCode::A);
+        app.step(0.05);
+    }
+    app.press_key(VirtualKeyCode::Escape);
+    app.step(0.016);
+}
+
+#[test]
+fn record_then_replay() {
+    let mut app = HeadlessApplication::new(MainMenu::default());
+    app.start_recording();
+    play(&mut app);
+    let recording = app.take_recording().unwrap();
+    assert!(recording.frames.iter().all(|x| !x.events.is_empty()));
+    let sessions = app.window().history.sessions().to_vec();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].clicks, 5);
+
+    let mut replayed = HeadlessApplication::new(MainMenu::default());
+    replayed.start_recording();
+    replayed.replay(&recording);
+    assert_eq!(replayed.take_recording().unwrap(), recording);
+    assert_eq!(replayed.state_count(), 1);
+    let again = replayed.window().history.sessions();
+    assert_eq!(again.len(), 1);
+    assert_eq!((again[0].clicks, &again[0].intervals), (sessions[0].clicks, &sessions[0].intervals));
+}
+
+#[test]
+fn replay_takes_one_frame_a_loop() {
+    let event = |ms| InputEvent { time: Duration::from_millis(ms), kind: InputEventKind::Key { key: VirtualKeyCode::A, pressed: true, repeat: false, synthetic: false } };
+    let mut recording = InputRecording::new([1.0, 1.0]);
+    recording.frames.push(RecordedFrame { time: Duration::ZERO, events: vec![event(0)] });
+    recording.frames.push(RecordedFrame { time: Duration::from_millis(1), events: vec![event(1)] });
+    let mut replay = InputReplay::new(recording.clone());
+    let clock = replay.clock();
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(replay.next_frame().as_ref(), Some(&recording.frames[0]));
+    assert_eq!(clock.now(), Duration::ZERO);
+    assert_eq!(replay.next_frame().as_ref(), Some(&recording.frames[1]));
+    assert_eq!(clock.now(), Duration::from_millis(1));
+    assert!(replay.is_finished());
+    assert_eq!(replay.next_frame(), None);
+    assert!(clock.now() >= Duration::from_millis(5));
+}